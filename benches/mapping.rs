@@ -0,0 +1,42 @@
+use ascii::{CHARS, Frame};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+fn frame() -> Frame<'static> {
+    ascii::image::get_pixels("examples/torii-gate-japan.jpg", None)
+}
+
+fn mapping(c: &mut Criterion) {
+    let frame = frame();
+
+    let mut group = c.benchmark_group("map frame to symbols");
+    group.bench_function("get_lightness + symbol", |b| {
+        b.iter(|| {
+            let mut symbols = Vec::with_capacity(frame.width * frame.height);
+            for y in 0..frame.height {
+                for (r, g, b) in frame.row_pixels(y) {
+                    symbols.push(ascii::symbol(ascii::get_lightness(r, g, b)));
+                }
+            }
+            symbols
+        })
+    });
+    group.bench_function("symbol_index", |b| {
+        b.iter(|| {
+            let mut symbols = Vec::with_capacity(frame.width * frame.height);
+            for y in 0..frame.height {
+                for (r, g, b) in frame.row_pixels(y) {
+                    symbols.push(CHARS[ascii::symbol_index(r, g, b)]);
+                }
+            }
+            symbols
+        })
+    });
+    let mut indices = Vec::new();
+    group.bench_function("symbol_indices", |b| {
+        b.iter(|| ascii::symbol_indices(&frame, &mut indices))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, mapping);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+use ab_glyph::FontRef;
+use ascii::image::{Layout, RenderOptions, Renderer};
+use criterion::{Criterion, criterion_group, criterion_main};
+use imageproc::drawing::draw_text_mut;
+use imageproc::image::{Rgb, RgbImage};
+
+// ASCII_FONT=/path/to/font.ttf cargo bench --bench render
+fn font_data() -> Vec<u8> {
+    let path = std::env::var("ASCII_FONT")
+        .unwrap_or("/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf".to_string());
+    std::fs::read(&path).unwrap_or_else(|err| panic!("failed to read font {path}: {err}"))
+}
+
+/// Renders every cell with `draw_text_mut`, like `get_image_buf` did before the glyph atlas.
+fn draw_text_per_cell(font: &FontRef, layout: &Layout, frame: &ascii::Frame) -> RgbImage {
+    let (width, height) = layout.image_size(frame.width, frame.height);
+    let mut image = RgbImage::new(width, height);
+    for y in 0..frame.height {
+        for (x, (r, g, b)) in frame.row_pixels(y).enumerate() {
+            let cell = ascii::Cell::new(r, g, b);
+            draw_text_mut(
+                &mut image,
+                Rgb([r, g, b]),
+                (x as u32 * layout.cell_width) as i32,
+                (y as u32 * layout.cell_height) as i32,
+                layout.scale,
+                font,
+                &format!("{0}{0}", cell.symbol),
+            );
+        }
+    }
+    image
+}
+
+fn render(c: &mut Criterion) {
+    let data = font_data();
+    let font = FontRef::try_from_slice(&data).unwrap();
+    let frame = ascii::image::get_pixels("examples/torii-gate-japan.jpg", Some(240.0));
+    let options = RenderOptions::default();
+    let renderer = Renderer::new(&font, &options);
+
+    let mut group = c.benchmark_group("render frame");
+    group.bench_function("draw_text_mut per cell", |b| {
+        b.iter(|| draw_text_per_cell(&font, renderer.layout(), &frame))
+    });
+    group.bench_function("glyph atlas", |b| b.iter(|| renderer.render(&frame)));
+    group.bench_function("glyph atlas (incl. rasterizing)", |b| {
+        b.iter(|| Renderer::new(&font, &options).render(&frame))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,264 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use crate::encode::{self, EncoderOptions, FFmpegEncoder, VideoCodec};
+
+use ffmpeg::{Rational, Rescale};
+use imageproc::image::RgbImage;
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::time::Duration;
+
+/// Format of an animated image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    WebP,
+}
+
+impl AnimationFormat {
+    /// Get the format from the extension of `path` (`gif`, `apng` or `png` and `webp`).
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "gif" => Some(Self::Gif),
+            "apng" | "png" => Some(Self::Apng),
+            "webp" => Some(Self::WebP),
+            _ => None,
+        }
+    }
+}
+
+/// Options of `video::draw_to_animation`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationOptions {
+    /// Only every `frame_step`th frame is rendered (e.g. `2` halves the frame rate).
+    pub frame_step: usize,
+    /// Only the first `max_duration` of the input is rendered, `None` renders all of it.
+    pub max_duration: Option<Duration>,
+    /// Speed of the GIF color quantization (`1..=30`), slower finds better palettes.
+    pub gif_speed: i32,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            frame_step: 1,
+            max_duration: None,
+            gif_speed: 10,
+        }
+    }
+}
+
+/// Writes rendered frames into an animated image, which loops forever.
+pub enum AnimationWriter {
+    Gif(GifWriter),
+    /// APNG and animated WebP, encoded by ffmpeg.
+    FFmpeg(FFmpegEncoder),
+}
+
+impl AnimationWriter {
+    /// Creates the animated image at `path` for frames of `width` x `height`, with timestamps
+    /// in `time_base` units.
+    pub fn new(
+        path: &str,
+        format: AnimationFormat,
+        width: u32,
+        height: u32,
+        frame_rate: Rational,
+        time_base: Rational,
+        options: &AnimationOptions,
+    ) -> io::Result<Self> {
+        let codec = match format {
+            AnimationFormat::Gif => {
+                let writer = GifWriter::new(path, width, height, time_base, options.gif_speed)?;
+                return Ok(Self::Gif(writer));
+            }
+            AnimationFormat::Apng => VideoCodec::Apng,
+            AnimationFormat::WebP => VideoCodec::WebP,
+        };
+        let encoder = encode::find_encoder(codec).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("no ffmpeg encoder for {format:?}"),
+            )
+        })?;
+        let options = EncoderOptions {
+            codec,
+            keyframe_interval: 0,
+            ..Default::default()
+        };
+        let (width, height) = encode::output_size(width, height, None);
+        FFmpegEncoder::new(
+            path, encoder, width, height, frame_rate, time_base, &options,
+        )
+        .map(Self::FFmpeg)
+        .map_err(io::Error::other)
+    }
+
+    /// Writes the next frame, `pts` is its presentation timestamp.
+    pub fn write(&mut self, image: RgbImage, pts: i64) -> io::Result<()> {
+        match self {
+            Self::Gif(writer) => writer.write(&image, pts),
+            Self::FFmpeg(encoder) => {
+                let (width, height) = encode::output_size(image.width(), image.height(), None);
+                let image = encode::pad(image, width, height);
+                encoder.encode(image.as_raw(), pts).map_err(io::Error::other)
+            }
+        }
+    }
+
+    /// Writes the last frame and finishes the file.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Gif(writer) => writer.finish(),
+            Self::FFmpeg(encoder) => encoder.finish().map_err(io::Error::other),
+        }
+    }
+}
+
+/// Delays of GIF frames are in centiseconds.
+const GIF_TIME_BASE: Rational = Rational(1, 100);
+
+/// Writes an animated GIF, every frame gets its own palette quantized from its colors.
+pub struct GifWriter {
+    encoder: gif::Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+    speed: i32,
+    time_base: Rational,
+    /// The last quantized frame, it's written once its delay is known.
+    pending: Option<gif::Frame<'static>>,
+    /// Delay of the written frames in centiseconds.
+    written: u64,
+    frames: u64,
+}
+
+impl GifWriter {
+    pub fn new(
+        path: &str,
+        width: u32,
+        height: u32,
+        time_base: Rational,
+        speed: i32,
+    ) -> io::Result<Self> {
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{width}x{height} is too large for a GIF"),
+                ));
+            }
+        };
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, width, height, &[]).map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            speed: speed.clamp(1, 30),
+            time_base,
+            pending: None,
+            written: 0,
+            frames: 0,
+        })
+    }
+
+    /// Quantizes the next frame and writes the previous one, which lasts until `pts`.
+    pub fn write(&mut self, image: &RgbImage, pts: i64) -> io::Result<()> {
+        let frame = gif::Frame::from_rgb_speed(self.width, self.height, image.as_raw(), self.speed);
+        let end = pts.rescale(self.time_base, GIF_TIME_BASE).max(0) as u64;
+        self.write_pending(end)?;
+        self.pending = Some(frame);
+        Ok(())
+    }
+
+    /// Writes the last frame (with the average delay) and finishes the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        let delay = self.written.checked_div(self.frames).unwrap_or(10);
+        self.write_pending(self.written + delay)?;
+        self.encoder.into_inner().map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn write_pending(&mut self, end: u64) -> io::Result<()> {
+        let Some(mut frame) = self.pending.take() else {
+            return Ok(());
+        };
+        // most viewers slow down frames with shorter delays, the following frames catch up
+        let delay = end.saturating_sub(self.written).clamp(2, u16::MAX as u64);
+        frame.delay = delay as u16;
+        self.written += delay;
+        self.frames += 1;
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::TempPath;
+
+    use imageproc::image::codecs::gif::GifDecoder;
+    use imageproc::image::{AnimationDecoder, Rgb};
+
+    use std::io::BufReader;
+
+    #[test]
+    fn gif_delays_follow_timestamps() {
+        let file = TempPath::new("gif-delays.gif");
+        let path = file.as_str();
+
+        // 30 fps in a time base of milliseconds
+        let mut writer = GifWriter::new(path, 8, 4, Rational(1, 1000), 10).unwrap();
+        for (i, pts) in [0, 33, 67, 100, 133].into_iter().enumerate() {
+            let image = RgbImage::from_pixel(8, 4, Rgb([i as u8 * 50, 0, 0]));
+            writer.write(&image, pts).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let decoder = GifDecoder::new(BufReader::new(File::open(path).unwrap())).unwrap();
+        let delays: Vec<u32> = decoder
+            .into_frames()
+            .map(|frame| frame.unwrap().delay().numer_denom_ms().0)
+            .collect();
+        assert_eq!(delays, [30, 40, 30, 30, 30]);
+    }
+
+    #[test]
+    fn png_extension_is_animated() {
+        ffmpeg::init().unwrap();
+        let file = TempPath::new("animation.png");
+        let path = file.as_str();
+
+        let options = AnimationOptions::default();
+        let format = AnimationFormat::from_path(path).unwrap();
+        let mut writer =
+            AnimationWriter::new(path, format, 8, 4, Rational(10, 1), Rational(1, 10), &options)
+                .unwrap();
+        for pts in 0..3 {
+            let image = RgbImage::from_pixel(8, 4, Rgb([pts as u8 * 100, 0, 0]));
+            writer.write(image, pts).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let ictx = ffmpeg::format::input(&path).unwrap();
+        assert_eq!(ictx.format().name(), "apng");
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(AnimationFormat::from_path("out.GIF"), Some(AnimationFormat::Gif));
+        assert_eq!(AnimationFormat::from_path("out.apng"), Some(AnimationFormat::Apng));
+        assert_eq!(AnimationFormat::from_path("out.png"), Some(AnimationFormat::Apng));
+        assert_eq!(AnimationFormat::from_path("out.webp"), Some(AnimationFormat::WebP));
+        assert_eq!(AnimationFormat::from_path("out.mp4"), None);
+    }
+}
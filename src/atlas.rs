@@ -0,0 +1,111 @@
+use crate::Cell;
+use crate::image::Layout;
+
+use ab_glyph::Font;
+use imageproc::image::RgbImage;
+
+use std::collections::HashMap;
+
+/// Pre-rasterized coverage masks of the symbols, one `cell_width * cell_height` mask per symbol.
+///
+/// The masks are only valid for the `Layout` (font size and cell size) the atlas was created
+/// with.
+#[derive(Debug, Clone)]
+pub struct GlyphAtlas {
+    layout: Layout,
+    masks: HashMap<char, Vec<u8>>,
+}
+
+impl GlyphAtlas {
+    /// Rasterizes the cells of all `symbols` with the given `font` and `layout`.
+    pub fn new(font: &impl Font, layout: Layout, symbols: &[char]) -> Self {
+        let mut masks = HashMap::with_capacity(symbols.len());
+        for &symbol in symbols {
+            // draw the cell in white, so the red channel is the coverage
+            let mut cell = RgbImage::new(layout.cell_width, layout.cell_height);
+            let white = Cell {
+                symbol,
+                fg: (255, 255, 255),
+                bg: (0, 0, 0),
+            };
+            layout.draw_cell(&mut cell, font, 0, 0, &white);
+            let mask = cell.pixels().map(|p| p.0[0]).collect();
+            masks.insert(symbol, mask);
+        }
+        Self { layout, masks }
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// The coverage mask of `symbol`, `None` if it wasn't rasterized.
+    pub fn mask(&self, symbol: char) -> Option<&[u8]> {
+        self.masks.get(&symbol).map(|m| m.as_slice())
+    }
+
+    /// Tints the mask of the `cell` with its color and blends it into `band` at `column`.
+    ///
+    /// `band` are the `cell_height` image rows of one row of cells, `width` is the width of the
+    /// image in pixels. Returns `false` if the symbol of the cell isn't in the atlas.
+    pub fn blit(&self, band: &mut [u8], width: usize, column: usize, cell: &Cell) -> bool {
+        let Some(mask) = self.mask(cell.symbol) else {
+            return false;
+        };
+        let cell_width = self.layout.cell_width as usize;
+        let (r, g, b) = cell.fg;
+        let color = [r as u16, g as u16, b as u16];
+        for (y, coverage) in mask.chunks_exact(cell_width).enumerate() {
+            let start = y * width * 3 + column * cell_width * 3;
+            let dst = &mut band[start..start + cell_width * 3];
+            for (pixel, &c) in dst.chunks_exact_mut(3).zip(coverage) {
+                if c == 0 {
+                    continue;
+                }
+                let c = c as u16;
+                for (d, v) in pixel.iter_mut().zip(color) {
+                    *d = ((*d as u16 * (255 - c) + v * c + 127) / 255) as u8;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blit_tints_coverage() {
+        let layout = Layout {
+            metrics: crate::image::FontMetrics {
+                advance: 1.0,
+                ascent: 1.0,
+                descent: 0.0,
+                line_gap: 0.0,
+            },
+            scale: 1.0.into(),
+            cell_width: 2,
+            cell_height: 2,
+            baseline: 1.0,
+            offset: 0.0,
+        };
+        let atlas = GlyphAtlas {
+            layout,
+            masks: HashMap::from([('#', vec![255, 0, 0, 51])]),
+        };
+        // two cells wide
+        let mut band = vec![0; 2 * 4 * 3];
+        let cell = Cell {
+            symbol: '#',
+            fg: (200, 100, 50),
+            bg: (0, 0, 0),
+        };
+        assert!(atlas.blit(&mut band, 4, 1, &cell));
+        assert_eq!(&band[6..12], &[200, 100, 50, 0, 0, 0]);
+        assert_eq!(&band[18..24], &[0, 0, 0, 40, 20, 10]);
+        assert!(band[..6].iter().all(|v| *v == 0));
+        assert!(!atlas.blit(&mut band, 4, 0, &Cell::new(0, 0, 0)));
+    }
+}
@@ -0,0 +1,755 @@
+use crate::clock::AudioClock;
+use crate::controls::AudioControls;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+
+#[cfg(test)]
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Plays the samples of a playback and publishes them to its `AudioClock`.
+///
+/// Sinks take the interleaved `f32` samples from the consumer of a ring buffer they are created
+/// with, in the sample rate and channel count they report.
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+    fn channels(&self) -> u16;
+    fn clock(&self) -> &AudioClock;
+    /// Starts (or resumes) playing.
+    fn play(&mut self) -> Result<(), String>;
+    fn pause(&mut self) -> Result<(), String>;
+}
+
+/// Where the audio of a playback goes.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum AudioBackend {
+    /// The default output device.
+    #[default]
+    Cpal,
+    /// Nowhere, the clock advances in real time.
+    Null,
+    /// A WAV file, the samples are written in real time.
+    Wav(PathBuf),
+    /// Nowhere, the samples are only taken when the sink is advanced.
+    #[cfg(test)]
+    Stepped(SteppedSink),
+}
+
+/// Sample rate of the null, WAV and stepped sinks.
+pub const SAMPLE_RATE: u32 = 48_000;
+/// Channels of the null, WAV and stepped sinks.
+pub const CHANNELS: u16 = 2;
+
+/// Opens a sink of the `backend`, which plays the samples of `consumer`.
+pub fn open_sink(
+    backend: &AudioBackend,
+    consumer: ringbuf::Consumer<f32>,
+    controls: AudioControls,
+) -> Result<Box<dyn AudioSink>, String> {
+    Ok(match backend {
+        AudioBackend::Cpal => Box::new(CpalSink::open(consumer, controls)?),
+        AudioBackend::Null => Box::new(RealtimeSink::null(
+            consumer,
+            controls,
+            SAMPLE_RATE,
+            CHANNELS,
+        )),
+        AudioBackend::Wav(path) => Box::new(
+            RealtimeSink::wav(path, consumer, controls, SAMPLE_RATE, CHANNELS)
+                .map_err(|err| format!("couldn't create {}: {err}", path.display()))?,
+        ),
+        #[cfg(test)]
+        AudioBackend::Stepped(sink) => Box::new(sink.open(consumer, controls)),
+    })
+}
+
+/// Sample formats of audio devices that are supported, the first one is preferred.
+const SAMPLE_FORMATS: [SampleFormat; 3] = [SampleFormat::F32, SampleFormat::I16, SampleFormat::U16];
+
+/// Fills the output buffer `data` with `samples` multiplied by `gain` (and converted to the
+/// sample format of the device), returns the number of samples taken from `samples`.
+///
+/// Samples that get too loud are clipped, the rest of the buffer is left as it is.
+fn fill_buffer<T>(data: &mut [T], samples: &mut ringbuf::Consumer<f32>, gain: f32) -> usize
+where
+    T: Sample + FromSample<f32>,
+{
+    let mut played = 0;
+    for d in data {
+        *d = match samples.pop() {
+            Some(sample) => T::from_sample((sample * gain).clamp(-1.0, 1.0)),
+            None => break,
+        };
+        played += 1;
+    }
+    played
+}
+
+/// Fills the output buffer `data` with `samples` at the volume of the `controls` and publishes the
+/// played samples, which start to play after `latency`, to the `clock` and the monitor of the
+/// `controls`. Returns the number of samples taken from `samples`.
+///
+/// Muted samples are played (and published) as silence.
+fn write_audio<T>(
+    data: &mut [T],
+    samples: &mut ringbuf::Consumer<f32>,
+    clock: &AudioClock,
+    controls: &AudioControls,
+    channels: usize,
+    latency: Duration,
+) -> usize
+where
+    T: Sample + FromSample<f32>,
+    f32: FromSample<T>,
+{
+    if controls.take_flush() {
+        samples.discard(samples.len());
+        controls.monitor().clear();
+    }
+    let played = fill_buffer(data, samples, controls.gain());
+    data[played..].fill(T::EQUILIBRIUM);
+    controls.monitor().push(
+        data[..played]
+            .iter()
+            .map(|sample| f32::from_sample(*sample)),
+        channels,
+    );
+    clock.advance((played / channels.max(1)) as u64, latency);
+    played
+}
+
+/// Pushes the `samples` to the `producer` of the ring buffer a sink plays from, waiting while it
+/// is full or the sink is dropping the samples from before a seek.
+///
+/// The samples are pushed as the sink takes them, so there can be more than fit into the buffer.
+pub fn push_samples(
+    producer: &mut ringbuf::Producer<f32>,
+    mut samples: &[f32],
+    controls: &AudioControls,
+) {
+    while !samples.is_empty() {
+        let pushed = match controls.is_flushing() {
+            true => 0,
+            false => producer.push_slice(samples),
+        };
+        samples = &samples[pushed..];
+        if pushed == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// Plays the samples on the default output device.
+pub struct CpalSink {
+    stream: cpal::Stream,
+    clock: AudioClock,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl CpalSink {
+    /// Opens the default output device in the best supported sample format, `Err` if there is
+    /// none.
+    pub fn open(consumer: ringbuf::Consumer<f32>, controls: AudioControls) -> Result<Self, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no output device available")?;
+        let config = output_config(&device)?;
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels();
+        let clock = AudioClock::new(sample_rate);
+        let stream_config = config.config();
+        let stream = match config.sample_format() {
+            SampleFormat::I16 => {
+                build_output_stream::<i16>(&device, &stream_config, consumer, &clock, controls)
+            }
+            SampleFormat::U16 => {
+                build_output_stream::<u16>(&device, &stream_config, consumer, &clock, controls)
+            }
+            _ => build_output_stream::<f32>(&device, &stream_config, consumer, &clock, controls),
+        }
+        .map_err(|err| err.to_string())?;
+        Ok(Self {
+            stream,
+            clock,
+            sample_rate,
+            channels,
+        })
+    }
+}
+
+impl AudioSink for CpalSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn clock(&self) -> &AudioClock {
+        &self.clock
+    }
+
+    fn play(&mut self) -> Result<(), String> {
+        self.stream.play().map_err(|err| err.to_string())
+    }
+
+    fn pause(&mut self) -> Result<(), String> {
+        self.stream.pause().map_err(|err| err.to_string())
+    }
+}
+
+/// The default output config of the `device` if its sample format is supported, the config with
+/// the best supported sample format (and the highest sample rate) otherwise.
+fn output_config(device: &cpal::Device) -> Result<cpal::SupportedStreamConfig, String> {
+    if let Ok(config) = device.default_output_config() {
+        if SAMPLE_FORMATS.contains(&config.sample_format()) {
+            return Ok(config);
+        }
+    }
+    device
+        .supported_output_configs()
+        .map_err(|err| err.to_string())?
+        .filter_map(|config| {
+            let rank = SAMPLE_FORMATS
+                .iter()
+                .position(|format| *format == config.sample_format())?;
+            Some((rank, config))
+        })
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, config)| config.with_max_sample_rate())
+        .ok_or_else(|| "the output device supports no F32, I16 or U16 config".to_string())
+}
+
+fn build_output_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut consumer: ringbuf::Consumer<f32>,
+    clock: &AudioClock,
+    controls: AudioControls,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+    f32: FromSample<T>,
+{
+    let clock = clock.clone();
+    let channels = config.channels as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], cbinfo| {
+            let timestamp = cbinfo.timestamp();
+            let latency = timestamp
+                .playback
+                .duration_since(&timestamp.callback)
+                .unwrap_or_default();
+            write_audio(data, &mut consumer, &clock, &controls, channels, latency);
+        },
+        |err| {
+            eprintln!("{err}");
+        },
+        None,
+    )
+}
+
+/// Takes the samples of a `RealtimeSink` or a `SteppedSink` as if they were played, and writes
+/// them to a WAV file (if there is one).
+struct Player {
+    consumer: ringbuf::Consumer<f32>,
+    controls: AudioControls,
+    clock: AudioClock,
+    channels: u16,
+    writer: Option<WavWriter<BufWriter<File>>>,
+    /// Whether the silence while there are no samples moves the clock as well, so it runs in real
+    /// time once the first samples are played.
+    counts_silence: bool,
+    buffer: Vec<f32>,
+}
+
+impl Player {
+    /// Plays `frames` frames (silence while there are no samples), returns the samples that were
+    /// played.
+    fn play(&mut self, frames: usize) -> io::Result<&[f32]> {
+        self.buffer.resize(frames * self.channels as usize, 0.0);
+        let played = write_audio(
+            &mut self.buffer,
+            &mut self.consumer,
+            &self.clock,
+            &self.controls,
+            self.channels as usize,
+            Duration::ZERO,
+        );
+        let silent = frames - played / self.channels as usize;
+        if self.counts_silence && silent > 0 && self.clock.frames() > 0 {
+            self.clock.advance(silent as u64, Duration::ZERO);
+        }
+        // only what was played, not the silence while there are no samples
+        if let Some(writer) = &mut self.writer {
+            writer.write(&self.buffer[..played])?;
+        }
+        Ok(&self.buffer[..played])
+    }
+
+    /// Writes the samples that are still buffered and finishes the WAV file.
+    fn finish(mut self) -> io::Result<()> {
+        let Some(mut writer) = self.writer else {
+            return Ok(());
+        };
+        self.buffer.resize(self.consumer.len(), 0.0);
+        let played = fill_buffer(&mut self.buffer, &mut self.consumer, self.controls.gain());
+        writer.write(&self.buffer[..played])?;
+        writer.finish().map(drop)
+    }
+}
+
+/// How often the `RealtimeSink` takes samples.
+const PERIOD: Duration = Duration::from_millis(10);
+
+/// Takes the samples in real time on its own thread, like an audio device without latency, and
+/// discards them or writes them to a WAV file.
+///
+/// The samples that are still buffered when it's dropped are written as well.
+pub struct RealtimeSink {
+    clock: AudioClock,
+    sample_rate: u32,
+    channels: u16,
+    playing: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl RealtimeSink {
+    /// A sink that discards the samples.
+    pub fn null(
+        consumer: ringbuf::Consumer<f32>,
+        controls: AudioControls,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Self {
+        Self::new(None, consumer, controls, sample_rate, channels)
+    }
+
+    /// A sink that writes the samples to the WAV file at `path`.
+    pub fn wav(
+        path: impl AsRef<Path>,
+        consumer: ringbuf::Consumer<f32>,
+        controls: AudioControls,
+        sample_rate: u32,
+        channels: u16,
+    ) -> io::Result<Self> {
+        let writer = WavWriter::create(path, sample_rate, channels)?;
+        Ok(Self::new(
+            Some(writer),
+            consumer,
+            controls,
+            sample_rate,
+            channels,
+        ))
+    }
+
+    fn new(
+        writer: Option<WavWriter<BufWriter<File>>>,
+        consumer: ringbuf::Consumer<f32>,
+        controls: AudioControls,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Self {
+        let clock = AudioClock::new(sample_rate);
+        let playing = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut player = Player {
+            consumer,
+            controls,
+            clock: clock.clone(),
+            channels,
+            // nothing plays the samples of the null sink, its clock doesn't wait for them
+            counts_silence: writer.is_none(),
+            writer,
+            buffer: Vec::new(),
+        };
+        let thread = {
+            let (playing, stop) = (playing.clone(), stop.clone());
+            thread::spawn(move || {
+                // frames taken since `since`
+                let (mut since, mut taken) = (Instant::now(), 0u64);
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(PERIOD);
+                    if !playing.load(Ordering::Relaxed) {
+                        (since, taken) = (Instant::now(), 0);
+                        continue;
+                    }
+                    let due = (since.elapsed().as_secs_f64() * sample_rate as f64) as u64;
+                    player.play((due - taken) as usize)?;
+                    taken = due;
+                }
+                player.finish()
+            })
+        };
+        Self {
+            clock,
+            sample_rate,
+            channels,
+            playing,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl AudioSink for RealtimeSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn clock(&self) -> &AudioClock {
+        &self.clock
+    }
+
+    fn play(&mut self) -> Result<(), String> {
+        self.playing.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), String> {
+        self.playing.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for RealtimeSink {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            match thread.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => eprintln!("Couldn't write the audio: {err}"),
+                Err(_) => eprintln!("audio thread panicked"),
+            }
+        }
+    }
+}
+
+/// Discards the samples, but only plays them when it's advanced by a number of frames, so its
+/// (stepped) clock moves exactly by the played frames and stands in between.
+///
+/// Clones share the sink: one is passed to the playback (as `AudioBackend::Stepped`), another one
+/// advances it. Only tests pace a playback by hand.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct SteppedSink {
+    clock: AudioClock,
+    playing: Arc<AtomicBool>,
+    /// `None` until the sink is opened.
+    player: Arc<Mutex<Option<Player>>>,
+}
+
+#[cfg(test)]
+impl SteppedSink {
+    pub fn new() -> Self {
+        Self {
+            clock: AudioClock::stepped(SAMPLE_RATE),
+            playing: Arc::new(AtomicBool::new(false)),
+            player: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Plays the next `frames` frames if it's playing, returns the played samples (fewer if there
+    /// aren't enough).
+    pub fn advance(&self, frames: usize) -> Vec<f32> {
+        let mut player = self.player.lock().expect("stepped sink was poisoned");
+        match (&mut *player, self.playing.load(Ordering::Relaxed)) {
+            (Some(player), true) => player
+                .play(frames)
+                .expect("stepped sink doesn't write a file")
+                .to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Plays the samples of `consumer` from now on.
+    fn open(&self, consumer: ringbuf::Consumer<f32>, controls: AudioControls) -> Self {
+        *self.player.lock().expect("stepped sink was poisoned") = Some(Player {
+            consumer,
+            controls,
+            clock: self.clock.clone(),
+            channels: CHANNELS,
+            writer: None,
+            counts_silence: false,
+            buffer: Vec::new(),
+        });
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+impl Default for SteppedSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+impl fmt::Debug for SteppedSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SteppedSink")
+            .field("frames", &self.clock.frames())
+            .finish()
+    }
+}
+
+/// Clones are the same sink.
+#[cfg(test)]
+impl PartialEq for SteppedSink {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.player, &other.player)
+    }
+}
+
+#[cfg(test)]
+impl Eq for SteppedSink {}
+
+#[cfg(test)]
+impl AudioSink for SteppedSink {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn clock(&self) -> &AudioClock {
+        &self.clock
+    }
+
+    fn play(&mut self) -> Result<(), String> {
+        self.playing.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), String> {
+        self.playing.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Writes interleaved `f32` samples as a 16 bit PCM WAV file.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    /// Bytes of samples written so far.
+    len: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate, channels)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Writes the header, the sizes in it are filled in by `finish`.
+    pub fn new(mut out: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let block_align = channels * 2;
+        out.write_all(b"RIFF")?;
+        out.write_all(&36u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&channels.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { out, len: 0 })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            self.out
+                .write_all(&i16::from_sample(*sample).to_le_bytes())?;
+        }
+        self.len += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    /// Fills in the sizes of the header.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + self.len).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.len.to_le_bytes())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clock::Clock;
+    use crate::testutil::TempPath;
+
+    use ringbuf::RingBuffer;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn fill_converts_samples() {
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(8).split();
+        producer.push_slice(&[0.0, 1.0, -1.0]);
+        let mut data = [1i16; 4];
+        assert_eq!(fill_buffer(&mut data, &mut consumer, 1.0), 3);
+        assert_eq!(data[..3], [0, i16::MAX, i16::MIN]);
+
+        producer.push_slice(&[0.0, 1.0]);
+        let mut data = [0u16; 2];
+        assert_eq!(fill_buffer(&mut data, &mut consumer, 1.0), 2);
+        assert_eq!(data, [u16::EQUILIBRIUM, u16::MAX]);
+
+        // muted samples are consumed
+        producer.push_slice(&[0.5, 0.5]);
+        let mut data = [1.0f32; 2];
+        assert_eq!(fill_buffer(&mut data, &mut consumer, 0.0), 2);
+        assert_eq!(data, [0.0, 0.0]);
+        assert!(consumer.is_empty());
+
+        // louder samples are clipped
+        producer.push_slice(&[0.25, -0.75]);
+        let mut data = [0.0f32; 2];
+        assert_eq!(fill_buffer(&mut data, &mut consumer, 2.0), 2);
+        assert_eq!(data, [0.5, -1.0]);
+    }
+
+    #[test]
+    fn write_audio_applies_the_volume() {
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(8).split();
+        producer.push_slice(&[0.5, 0.25, -0.5, -0.25]);
+        let clock = AudioClock::new(8_000);
+        let controls = AudioControls::default();
+        controls.set_volume(0.5);
+        let mut data = [1.0f32; 6];
+        let played = write_audio(
+            &mut data,
+            &mut consumer,
+            &clock,
+            &controls,
+            2,
+            Duration::ZERO,
+        );
+        assert_eq!(played, 4);
+        assert_eq!(data, [0.25, 0.125, -0.25, -0.125, 0.0, 0.0]);
+        assert_eq!(clock.frames(), 2);
+        // the monitor gets the played samples, mixed down to mono
+        assert_eq!(controls.monitor().latest(2), [0.1875, -0.1875]);
+    }
+
+    #[test]
+    fn pushes_more_samples_than_fit() {
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(8).split();
+        let samples: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let played = thread::spawn(move || {
+            let mut played = Vec::new();
+            while played.len() < 100 {
+                match consumer.pop() {
+                    Some(sample) => played.push(sample),
+                    None => thread::yield_now(),
+                }
+            }
+            played
+        });
+        push_samples(&mut producer, &samples, &AudioControls::default());
+        assert_eq!(played.join().unwrap(), samples);
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 8000, 2).unwrap();
+        writer.write(&[0.0, 0.5, -0.5, 1.0]).unwrap();
+        let wav = writer.finish().unwrap().into_inner();
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(wav[4..8], 44u32.to_le_bytes());
+        assert_eq!(wav[24..28], 8000u32.to_le_bytes());
+        assert_eq!(wav[28..32], 32000u32.to_le_bytes());
+        assert_eq!(wav[40..44], 8u32.to_le_bytes());
+        assert_eq!(wav[46..48], 16384i16.to_le_bytes());
+    }
+
+    #[test]
+    fn stepped_sink_plays_what_it_is_advanced_by() {
+        let (mut producer, consumer) = RingBuffer::<f32>::new(48_000).split();
+        producer.push_slice(&[0.25; 9_600]);
+        let sink = SteppedSink::new();
+        let backend = AudioBackend::Stepped(sink.clone());
+        let mut opened = open_sink(&backend, consumer, AudioControls::default()).unwrap();
+        assert_eq!(opened.clock().now(), Duration::ZERO);
+        // nothing is played before `play`
+        assert!(sink.advance(480).is_empty());
+        assert_eq!(sink.clock().frames(), 0);
+
+        opened.play().unwrap();
+        assert_eq!(sink.advance(480), [0.25; 960]);
+        assert_eq!(sink.clock().frames(), 480);
+        assert_eq!(opened.clock().now(), Duration::from_millis(10));
+        // fewer frames than asked for, once the samples run out
+        assert_eq!(sink.advance(48_000).len(), 4_320 * 2);
+        assert_eq!(sink.clock().now(), Duration::from_millis(100));
+        assert!(sink.advance(480).is_empty());
+        assert_eq!(sink.clock().now(), Duration::from_millis(100));
+
+        opened.pause().unwrap();
+        producer.push_slice(&[0.5; 2]);
+        assert!(sink.advance(1).is_empty());
+        opened.play().unwrap();
+        assert_eq!(sink.advance(1), [0.5; 2]);
+        assert_eq!(sink.clock().frames(), 4_801);
+    }
+
+    #[test]
+    fn null_sink_runs_in_real_time() {
+        let (mut producer, consumer) = RingBuffer::<f32>::new(1_000).split();
+        let mut sink = RealtimeSink::null(consumer, AudioControls::default(), 8_000, 2);
+        sink.play().unwrap();
+        // the clock stands until the first samples are played
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(sink.clock().frames(), 0);
+
+        // 5 ms of samples, then the clock keeps running without them
+        producer.push_slice(&[0.5; 80]);
+        thread::sleep(Duration::from_millis(100));
+        let frames = sink.clock().frames();
+        assert!(frames >= 400, "{frames}");
+    }
+
+    #[test]
+    fn wav_sink_writes_everything() {
+        let path = TempPath::new("wav-sink.wav");
+        let (mut producer, consumer) = RingBuffer::<f32>::new(1_000).split();
+        producer.push_slice(&[0.5; 600]);
+        let mut sink =
+            RealtimeSink::wav(path.path(), consumer, AudioControls::default(), 8_000, 2).unwrap();
+        sink.play().unwrap();
+        // the samples that weren't played yet are written when it's dropped
+        drop(sink);
+        let wav = std::fs::read(path.path()).unwrap();
+        assert_eq!(wav.len(), 44 + 600 * 2);
+        assert_eq!(wav[44..46], 16384i16.to_le_bytes());
+    }
+}
@@ -0,0 +1,419 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Position on the presentation timeline of a playback.
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// Clock driven by the audio output: the position is the number of samples played divided by the
+/// sample rate.
+///
+/// The audio callback publishes every buffer with `advance`, between buffers (and once the audio
+/// ends) the clock runs in real time. It stands at the start until the first buffer is published.
+/// Clones share the position.
+///
+/// A `stepped` clock doesn't run between buffers, it is exactly at the end of the last one.
+#[derive(Debug, Clone)]
+pub struct AudioClock {
+    inner: Arc<AudioPosition>,
+}
+
+#[derive(Debug)]
+struct AudioPosition {
+    epoch: Instant,
+    sample_rate: u32,
+    stepped: bool,
+    /// Timestamp of the first sample in microseconds.
+    start: AtomicU64,
+    /// Frames (samples per channel) published so far.
+    total: AtomicU64,
+    /// Frames played before the last published buffer.
+    base: AtomicU64,
+    /// When the last published buffer starts to play, in nanoseconds since `epoch`.
+    base_at: AtomicU64,
+}
+
+impl AudioClock {
+    pub fn new(sample_rate: u32) -> Self {
+        Self::with_stepping(sample_rate, false)
+    }
+
+    /// A clock that only moves when buffers are published, for tests that pace a playback.
+    #[cfg(test)]
+    pub fn stepped(sample_rate: u32) -> Self {
+        Self::with_stepping(sample_rate, true)
+    }
+
+    fn with_stepping(sample_rate: u32, stepped: bool) -> Self {
+        Self {
+            inner: Arc::new(AudioPosition {
+                epoch: Instant::now(),
+                sample_rate,
+                stepped,
+                start: AtomicU64::new(0),
+                total: AtomicU64::new(0),
+                base: AtomicU64::new(0),
+                base_at: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Sets the timestamp of the first sample.
+    pub fn set_start(&self, start: Duration) {
+        self.inner
+            .start
+            .store(start.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Publishes a buffer with `frames` frames of the stream, which starts to play after
+    /// `latency`. Buffers without frames (silence) are ignored.
+    pub fn advance(&self, frames: u64, latency: Duration) {
+        if frames == 0 {
+            return;
+        }
+        let inner = &self.inner;
+        let before = inner.total.fetch_add(frames, Ordering::Relaxed);
+        let at = inner.epoch.elapsed() + latency;
+        inner.base.store(before, Ordering::Relaxed);
+        inner.base_at.store(at.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Number of frames published so far.
+    pub fn frames(&self) -> u64 {
+        self.inner.total.load(Ordering::Relaxed)
+    }
+
+    /// Moves the clock to `start`, where it stands until the next buffer is published (after
+    /// seeking or pausing).
+    pub fn reset(&self, start: Duration) {
+        let inner = &self.inner;
+        self.set_start(start);
+        inner.total.store(0, Ordering::Relaxed);
+        inner.base.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Clock for AudioClock {
+    fn now(&self) -> Duration {
+        let inner = &self.inner;
+        let start = Duration::from_micros(inner.start.load(Ordering::Relaxed));
+        let total = inner.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return start;
+        }
+        if inner.stepped {
+            let nanos = total as u128 * 1_000_000_000 / inner.sample_rate as u128;
+            return start + Duration::from_nanos(nanos as u64);
+        }
+        let base = inner.base.load(Ordering::Relaxed) as f64 / inner.sample_rate as f64;
+        let base_at = Duration::from_nanos(inner.base_at.load(Ordering::Relaxed));
+        // negative while the end of the previous buffer is still playing
+        let since = inner.epoch.elapsed().as_secs_f64() - base_at.as_secs_f64();
+        start + Duration::from_secs_f64((base + since).max(0.0))
+    }
+}
+
+/// Clock that runs in real time at a speed, it can be paused and moved.
+#[derive(Debug, Clone, Copy)]
+pub struct MediaClock {
+    anchor: Instant,
+    /// Position at `anchor`.
+    position: Duration,
+    speed: f64,
+    paused: bool,
+}
+
+impl MediaClock {
+    pub fn new() -> Self {
+        Self {
+            anchor: Instant::now(),
+            position: Duration::ZERO,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.set(self.now());
+        self.paused = paused;
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.set(self.now());
+        self.speed = speed;
+    }
+
+    /// Moves the clock to `position`.
+    pub fn set(&mut self, position: Duration) {
+        self.position = position;
+        self.anchor = Instant::now();
+    }
+}
+
+impl Default for MediaClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MediaClock {
+    fn now(&self) -> Duration {
+        match self.paused {
+            true => self.position,
+            false => self.position + self.anchor.elapsed().mul_f64(self.speed),
+        }
+    }
+}
+
+/// Clock of an interactive playback: it follows the audio while it plays at normal speed and
+/// runs on a `MediaClock` otherwise (without audio, paused or at other speeds).
+#[derive(Debug, Clone)]
+pub struct PlaybackClock {
+    media: MediaClock,
+    audio: Option<AudioClock>,
+}
+
+impl PlaybackClock {
+    pub fn new(audio: Option<AudioClock>) -> Self {
+        Self {
+            media: MediaClock::new(),
+            audio,
+        }
+    }
+
+    /// Whether the playback has audio.
+    pub fn has_audio(&self) -> bool {
+        self.audio.is_some()
+    }
+
+    /// Whether the position is the one of the audio clock.
+    pub fn follows_audio(&self) -> bool {
+        self.audio.is_some() && !self.media.is_paused() && self.media.speed() == 1.0
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.media.is_paused()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.update(|media| media.set_paused(paused));
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.media.speed()
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.update(|media| media.set_speed(speed));
+    }
+
+    /// Moves the clock to `position`, the audio clock stands there until the audio after it is
+    /// played.
+    pub fn seek(&mut self, position: Duration) {
+        self.media.set(position);
+        if let Some(audio) = &self.audio {
+            audio.reset(position);
+        }
+    }
+
+    /// Changes the media clock from the current position, the audio clock continues from there
+    /// if it's followed afterwards.
+    fn update(&mut self, change: impl FnOnce(&mut MediaClock)) {
+        let now = self.now();
+        self.media.set(now);
+        change(&mut self.media);
+        if let (true, Some(audio)) = (self.follows_audio(), &self.audio) {
+            audio.reset(now);
+        }
+    }
+}
+
+impl Clock for PlaybackClock {
+    fn now(&self) -> Duration {
+        match &self.audio {
+            Some(audio) if self.follows_audio() => audio.now(),
+            _ => self.media.now(),
+        }
+    }
+}
+
+/// What to do with a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Present the frame after waiting for the duration.
+    Present(Duration),
+    /// The frame is too late, skip it.
+    Drop,
+}
+
+/// Number of presented and dropped frames of a playback.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub presented: usize,
+    pub dropped: usize,
+}
+
+/// Longest sleep of `Pacer::wait`.
+pub const WAIT_SLICE: Duration = Duration::from_millis(20);
+
+/// Schedules frames by their presentation timestamp against a `Clock`.
+#[derive(Debug, Clone)]
+pub struct Pacer<C> {
+    clock: C,
+    /// Frames that are later than this are dropped.
+    max_lateness: Duration,
+}
+
+impl<C: Clock> Pacer<C> {
+    pub fn new(clock: C, max_lateness: Duration) -> Self {
+        Self {
+            clock,
+            max_lateness,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Decides when the frame with the presentation timestamp `pts` is shown.
+    pub fn schedule(&self, pts: Duration) -> Schedule {
+        let now = self.clock.now();
+        match pts.checked_sub(now) {
+            Some(early) => Schedule::Present(early),
+            None if now - pts > self.max_lateness => Schedule::Drop,
+            None => Schedule::Present(Duration::ZERO),
+        }
+    }
+
+    /// Sleeps until the frame with the presentation timestamp `pts` is due, but at most for
+    /// `WAIT_SLICE`, so the caller can handle the controls in between. Returns how much earlier
+    /// the frame still is, or `Drop` if it is too late.
+    pub fn wait(&self, pts: Duration) -> Schedule {
+        match self.schedule(pts) {
+            Schedule::Present(early) => {
+                std::thread::sleep(early.min(WAIT_SLICE));
+                Schedule::Present(early.saturating_sub(WAIT_SLICE))
+            }
+            Schedule::Drop => Schedule::Drop,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    struct ManualClock(Cell<Duration>);
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn audio_clock_follows_samples() {
+        let clock = AudioClock::new(48_000);
+        clock.set_start(Duration::from_secs(2));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now(), Duration::from_secs(2));
+
+        clock.advance(48_000, Duration::ZERO);
+        // the second buffer starts to play in 10 seconds
+        clock.advance(24_000, Duration::from_secs(10));
+        assert_eq!(clock.frames(), 72_000);
+        let now = clock.now();
+        assert!(now >= Duration::from_secs(2), "{now:?}");
+        assert!(now < Duration::from_secs(3), "{now:?}");
+    }
+
+    #[test]
+    fn stepped_audio_clock_stands_between_buffers() {
+        let clock = AudioClock::stepped(48_000);
+        clock.set_start(Duration::from_secs(1));
+        clock.advance(480, Duration::ZERO);
+        assert_eq!(clock.now(), Duration::from_millis(1_010));
+        // the latency doesn't matter, the clock is at the end of the buffer
+        clock.advance(1_440, Duration::from_secs(10));
+        assert_eq!(clock.now(), Duration::from_millis(1_040));
+
+        clock.reset(Duration::from_secs(5));
+        assert_eq!(clock.now(), Duration::from_secs(5));
+        clock.advance(48_000, Duration::ZERO);
+        assert_eq!(clock.now(), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn playback_clock_pauses_and_seeks() {
+        let audio = AudioClock::new(48_000);
+        let mut clock = PlaybackClock::new(Some(audio.clone()));
+        audio.set_start(Duration::from_secs(3));
+        assert!(clock.follows_audio());
+        assert_eq!(clock.now(), Duration::from_secs(3));
+
+        clock.set_paused(true);
+        assert!(!clock.follows_audio());
+        let paused = clock.now();
+        assert!(paused - Duration::from_secs(3) < Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now(), paused);
+
+        clock.seek(Duration::from_secs(10));
+        assert_eq!(clock.now(), Duration::from_secs(10));
+        clock.set_paused(false);
+        // the audio stands at the new position until it plays
+        assert_eq!(audio.now(), Duration::from_secs(10));
+        assert_eq!(clock.now(), Duration::from_secs(10));
+
+        clock.set_speed(2.0);
+        std::thread::sleep(Duration::from_millis(20));
+        let now = clock.now();
+        assert!(now >= Duration::from_millis(10_040), "{now:?}");
+    }
+
+    #[test]
+    fn schedule_by_timestamp() {
+        let ms = Duration::from_millis;
+        let pacer = Pacer::new(ManualClock(Cell::new(ms(100))), ms(40));
+        assert_eq!(pacer.schedule(ms(150)), Schedule::Present(ms(50)));
+        assert_eq!(pacer.schedule(ms(100)), Schedule::Present(ms(0)));
+        // late, but not too late
+        assert_eq!(pacer.schedule(ms(60)), Schedule::Present(ms(0)));
+        assert_eq!(pacer.schedule(ms(59)), Schedule::Drop);
+
+        pacer.clock().0.set(ms(200));
+        assert_eq!(pacer.schedule(ms(150)), Schedule::Drop);
+    }
+
+    #[test]
+    fn wait_in_slices() {
+        let ms = Duration::from_millis;
+        let pacer = Pacer::new(ManualClock(Cell::new(ms(100))), ms(40));
+        assert_eq!(pacer.wait(ms(150)), Schedule::Present(ms(30)));
+        assert_eq!(pacer.wait(ms(110)), Schedule::Present(ms(0)));
+        assert_eq!(pacer.wait(ms(50)), Schedule::Drop);
+    }
+}
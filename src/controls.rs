@@ -0,0 +1,308 @@
+use crate::visualize::AudioMonitor;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Action requested by a key press during playback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Space: pause or resume.
+    TogglePause,
+    /// Arrow keys: seek by the number of seconds, left/right by 5 and down/up by 60.
+    Seek(i64),
+    /// `]`
+    Faster,
+    /// `[`
+    Slower,
+    /// `m`
+    ToggleMute,
+    /// `+` (or `=`)
+    VolumeUp,
+    /// `-`
+    VolumeDown,
+    /// `q` (or ctrl+c)
+    Quit,
+}
+
+/// Get the commands of the keys in `input` (as read from a terminal), unknown keys are ignored.
+///
+/// An escape sequence at the end of `input` may continue in the next read, it's returned unparsed.
+pub fn parse_keys(input: &[u8]) -> (Vec<Command>, &[u8]) {
+    let mut commands = Vec::new();
+    let mut i = 0;
+    while i < input.len() {
+        let command = match &input[i..] {
+            // arrow keys, `ESC [ x` or `ESC O x` (application cursor mode)
+            [0x1b, b'[' | b'O', key, ..] => {
+                i += 2;
+                match key {
+                    b'A' => Some(Command::Seek(60)),
+                    b'B' => Some(Command::Seek(-60)),
+                    b'C' => Some(Command::Seek(5)),
+                    b'D' => Some(Command::Seek(-5)),
+                    _ => None,
+                }
+            }
+            [0x1b] | [0x1b, b'[' | b'O'] => return (commands, &input[i..]),
+            [b' ', ..] => Some(Command::TogglePause),
+            [b']', ..] => Some(Command::Faster),
+            [b'[', ..] => Some(Command::Slower),
+            [b'm' | b'M', ..] => Some(Command::ToggleMute),
+            [b'+' | b'=', ..] => Some(Command::VolumeUp),
+            [b'-', ..] => Some(Command::VolumeDown),
+            // ctrl+c doesn't interrupt while the keys are read
+            [b'q' | b'Q' | 0x03, ..] => Some(Command::Quit),
+            _ => None,
+        };
+        commands.extend(command);
+        i += 1;
+    }
+    (commands, &[])
+}
+
+/// Playback speeds `[` and `]` step through.
+pub const SPEEDS: [f64; 8] = [0.25, 0.5, 0.75, 1.0, 1.25, 1.5, 2.0, 4.0];
+
+/// The next speed of `SPEEDS` after `speed`, it stays at the slowest and fastest speed.
+pub fn next_speed(speed: f64, faster: bool) -> f64 {
+    if faster {
+        SPEEDS
+            .into_iter()
+            .find(|s| *s > speed)
+            .unwrap_or(SPEEDS[SPEEDS.len() - 1])
+    } else {
+        SPEEDS
+            .into_iter()
+            .rev()
+            .find(|s| *s < speed)
+            .unwrap_or(SPEEDS[0])
+    }
+}
+
+/// Formats `time` as `m:ss`, or `h:mm:ss` from an hour on.
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, m, s) => format!("{m}:{s:02}"),
+        (h, m, s) => format!("{h}:{m:02}:{s:02}"),
+    }
+}
+
+/// Volume `+` and `-` change it by.
+pub const VOLUME_STEP: f32 = 0.1;
+/// Highest volume, louder samples are clipped.
+pub const MAX_VOLUME: f32 = 2.0;
+
+/// State of the audio output shared with its callback.
+#[derive(Debug, Clone)]
+pub struct AudioControls {
+    muted: Arc<AtomicBool>,
+    /// Bits of the `f32` volume.
+    volume: Arc<AtomicU32>,
+    flush: Arc<AtomicBool>,
+    monitor: AudioMonitor,
+}
+
+impl Default for AudioControls {
+    fn default() -> Self {
+        Self {
+            muted: Arc::default(),
+            volume: Arc::new(AtomicU32::new(1f32.to_bits())),
+            flush: Arc::default(),
+            monitor: AudioMonitor::default(),
+        }
+    }
+}
+
+impl AudioControls {
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Factor the samples are multiplied by, `1.0` is the original volume.
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    /// Sets the volume, clamped to `0.0..=MAX_VOLUME`.
+    pub fn set_volume(&self, volume: f32) {
+        let volume = volume.clamp(0.0, MAX_VOLUME);
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Factor the samples are multiplied by, `0.0` if it's muted.
+    pub fn gain(&self) -> f32 {
+        match self.is_muted() {
+            true => 0.0,
+            false => self.volume(),
+        }
+    }
+
+    /// The samples that were played last.
+    pub fn monitor(&self) -> &AudioMonitor {
+        &self.monitor
+    }
+
+    /// Asks the callback to drop the buffered samples (after seeking).
+    pub fn flush(&self) {
+        self.flush.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the buffered samples should be dropped, resets the request.
+    pub fn take_flush(&self) -> bool {
+        self.flush.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether the callback hasn't dropped the buffered samples yet.
+    pub fn is_flushing(&self) -> bool {
+        self.flush.load(Ordering::Relaxed)
+    }
+}
+
+/// Reads key presses from the terminal without waiting for enter and without echoing them.
+///
+/// The terminal is restored when it's dropped.
+#[cfg(unix)]
+pub struct Keyboard {
+    original: libc::termios,
+    /// The start of an escape sequence the last read ended with.
+    pending: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl Keyboard {
+    /// Switches stdin to non-canonical mode, `None` if stdin is not a terminal.
+    pub fn new() -> Option<Self> {
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) != 1 {
+                return None;
+            }
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return None;
+            }
+            let mut raw = original;
+            // ctrl+c is read as a key (and quits), so the terminal is restored
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            // reads return immediately, even without input
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+            Some(Self {
+                original,
+                pending: Vec::new(),
+            })
+        }
+    }
+
+    /// Get the commands of the keys pressed since the last call, without blocking.
+    pub fn commands(&mut self) -> Vec<Command> {
+        let mut buffer = [0u8; 64];
+        let read =
+            unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len()) };
+        let mut input = std::mem::take(&mut self.pending);
+        if read > 0 {
+            input.extend_from_slice(&buffer[..read as usize]);
+        }
+        let (commands, rest) = parse_keys(&input);
+        self.pending = rest.to_vec();
+        commands
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Reads key presses from the terminal, only supported on unix.
+#[cfg(not(unix))]
+pub struct Keyboard;
+
+#[cfg(not(unix))]
+impl Keyboard {
+    pub fn new() -> Option<Self> {
+        None
+    }
+
+    pub fn commands(&mut self) -> Vec<Command> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_to_commands() {
+        assert_eq!(
+            parse_keys(b" q\x1b[C\x1b[Dx\x1b[A\x1bOB[]m+-\x03").0,
+            [
+                Command::TogglePause,
+                Command::Quit,
+                Command::Seek(5),
+                Command::Seek(-5),
+                Command::Seek(60),
+                Command::Seek(-60),
+                Command::Slower,
+                Command::Faster,
+                Command::ToggleMute,
+                Command::VolumeUp,
+                Command::VolumeDown,
+                Command::Quit,
+            ]
+        );
+        // a lone escape and other escape sequences
+        assert_eq!(parse_keys(b"\x1b\x1b[5~"), (vec![], &b""[..]));
+    }
+
+    #[test]
+    fn escape_sequence_split_across_reads() {
+        assert_eq!(
+            parse_keys(b" \x1b["),
+            (vec![Command::TogglePause], &b"\x1b["[..])
+        );
+        assert_eq!(parse_keys(b"q\x1b"), (vec![Command::Quit], &b"\x1b"[..]));
+        assert_eq!(parse_keys(b"\x1b[C"), (vec![Command::Seek(5)], &b""[..]));
+    }
+
+    #[test]
+    fn speed_steps() {
+        assert_eq!(next_speed(1.0, true), 1.25);
+        assert_eq!(next_speed(1.0, false), 0.75);
+        assert_eq!(next_speed(4.0, true), 4.0);
+        assert_eq!(next_speed(0.25, false), 0.25);
+    }
+
+    #[test]
+    fn volume_is_clamped() {
+        let controls = AudioControls::default();
+        assert_eq!(controls.gain(), 1.0);
+        controls.set_volume(5.0);
+        assert_eq!(controls.volume(), MAX_VOLUME);
+        controls.set_volume(-1.0);
+        assert_eq!(controls.volume(), 0.0);
+        controls.set_volume(0.5);
+        controls.set_muted(true);
+        assert_eq!(controls.gain(), 0.0);
+        assert_eq!(controls.volume(), 0.5);
+    }
+
+    #[test]
+    fn time_format() {
+        assert_eq!(format_time(Duration::from_secs(65)), "1:05");
+        assert_eq!(format_time(Duration::from_secs(3725)), "1:02:05");
+    }
+}
@@ -1,23 +1,572 @@
-use std::process::Command;
-
-pub fn convert(ogpath: &str, ipath: &str, opath: &str) {
-    let mut command = Command::new("ffmpeg");
-    command.args([
-        "-v", "error", "-stats", // Only show progress (or errors)
-        "-vn", "-i", ogpath, // get audio (or not video) from original video
-        "-an", "-i", ipath, // get video (or not audio) from input video
-        // "-c:v", "libx265", // video codec
-        // "-b:v", "700k", // 700k bitrate
-        // "-c:a", "libmp3lame", // audio codec
+extern crate ffmpeg_next as ffmpeg;
+
+use crate::streams::{self, StreamSelector};
+use crate::trim::TimeRange;
+
+use ffmpeg::format::context::{Input, Output};
+use ffmpeg::{ChannelLayout, Packet, Rational, Rescale, codec, encoder, filter, format, media};
+
+use std::time::SystemTime;
+
+/// How the audio of the original video gets into the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioMode {
+    /// Copy the audio if the output container supports its codec, re-encode it otherwise (and if
+    /// it's trimmed, so it's cut at the exact sample).
+    #[default]
+    Auto,
+    /// Copy the audio packets without decoding them.
+    Copy,
+    /// Decode the audio and encode it with the default audio codec of the output container.
+    Reencode,
+}
+
+/// Muxes the video of `ipath` with the audio of `ogpath` into `opath`, see `convert_with`.
+pub fn convert(ogpath: &str, ipath: &str, opath: &str) -> Result<(), ffmpeg::Error> {
+    convert_with(
+        ogpath,
+        ipath,
         opath,
-    ]);
-
-    if let Ok(mut child) = command.spawn() {
-        let s = std::time::SystemTime::now();
-        child.wait().expect("command was not running");
-        println!("Successfully converted {ipath} to {opath}, in {}ms",
-            std::time::SystemTime::now().duration_since(s).unwrap().as_millis());
-    } else {
-        println!("ffmpeg command didn't start");
+        AudioMode::default(),
+        &TimeRange::default(),
+        &StreamSelector::Best,
+    )
+}
+
+/// Muxes the video of `ipath` (copied, it is already encoded) with the audio stream of `ogpath`
+/// the `audio_stream` selector selects into `opath`.
+///
+/// The audio is trimmed to the `range` of `ogpath` the video was rendered from, copied audio can
+/// only be cut between packets. The output only contains the video if `ogpath` has no audio, it's
+/// an error if an explicitly selected stream doesn't exist.
+pub fn convert_with(
+    ogpath: &str,
+    ipath: &str,
+    opath: &str,
+    audio_mode: AudioMode,
+    range: &TimeRange,
+    audio_stream: &StreamSelector,
+) -> Result<(), ffmpeg::Error> {
+    let s = SystemTime::now();
+    let mut audio_ictx = format::input(&ogpath)?;
+    let mut video_ictx = format::input(&ipath)?;
+    let mut octx = format::output(&opath)?;
+
+    let (video_index, video_time_base, video_out) = {
+        let video = video_ictx
+            .streams()
+            .best(media::Type::Video)
+            .ok_or(ffmpeg::Error::StreamNotFound)?;
+        let mut ost = octx.add_stream(encoder::find(codec::Id::None))?;
+        ost.set_parameters(video.parameters());
+        // let the muxer pick the tag of the codec
+        unsafe {
+            (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+        }
+        (video.index(), video.time_base(), ost.index())
+    };
+
+    let start = crate::video::start_time(&audio_ictx) + range.start_micros();
+    let mut audio = match streams::select_stream(&audio_ictx, media::Type::Audio, audio_stream) {
+        Some(stream) => Some(AudioTrack::new(
+            &stream, start, &mut octx, opath, audio_mode, range,
+        )?),
+        None if *audio_stream == StreamSelector::Best => None,
+        None => return Err(ffmpeg::Error::StreamNotFound),
+    };
+    if !range.start.is_zero() {
+        audio_ictx.seek(start, ..start)?;
+    }
+
+    octx.set_metadata(audio_ictx.metadata().to_owned());
+    octx.write_header()?;
+
+    // the muxer may change the time bases of the streams in `write_header`
+    let video_out_time_base = stream_time_base(&octx, video_out);
+    if let Some(audio) = &mut audio {
+        audio.out_time_base = stream_time_base(&octx, audio.out);
+    }
+
+    let mut next_video = next_packet(&mut video_ictx, video_index)?;
+    let mut next_audio = match &audio {
+        Some(audio) => audio.next_packet(&mut audio_ictx)?,
+        None => None,
+    };
+    loop {
+        // write the packet that comes first, so the streams are interleaved
+        let video_first = match (&next_video, &next_audio, &audio) {
+            (None, None, _) => break,
+            (Some(v), Some(a), Some(audio)) => {
+                timestamp(v, video_time_base) <= timestamp(a, audio.time_base)
+            }
+            (v, _, _) => v.is_some(),
+        };
+        if video_first {
+            if let Some(mut packet) = next_video.take() {
+                packet.rescale_ts(video_time_base, video_out_time_base);
+                packet.set_position(-1);
+                packet.set_stream(video_out);
+                packet.write_interleaved(&mut octx)?;
+            }
+            next_video = next_packet(&mut video_ictx, video_index)?;
+        } else if let (Some(audio), Some(packet)) = (&mut audio, next_audio.take()) {
+            audio.write(packet, &mut octx)?;
+            next_audio = audio.next_packet(&mut audio_ictx)?;
+        }
+    }
+    if let Some(audio) = &mut audio {
+        audio.finish(&mut octx)?;
+    }
+    octx.write_trailer()?;
+
+    println!(
+        "Successfully converted {ipath} to {opath}, in {}ms",
+        SystemTime::now().duration_since(s).unwrap().as_millis()
+    );
+    Ok(())
+}
+
+/// Reads the next packet of the stream with the given `index`, `None` at the end of the input.
+fn next_packet(ictx: &mut Input, index: usize) -> Result<Option<Packet>, ffmpeg::Error> {
+    loop {
+        let mut packet = Packet::empty();
+        match packet.read(ictx) {
+            Ok(()) if packet.stream() == index => return Ok(Some(packet)),
+            Ok(()) => {}
+            Err(ffmpeg::Error::Eof) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Decoding timestamp of the `packet` in microseconds.
+fn timestamp(packet: &Packet, time_base: Rational) -> i64 {
+    packet
+        .dts()
+        .or(packet.pts())
+        .unwrap_or(0)
+        .rescale(time_base, ffmpeg::rescale::TIME_BASE)
+}
+
+fn stream_time_base(octx: &Output, index: usize) -> Rational {
+    octx.stream(index)
+        .expect("stream was added to the output")
+        .time_base()
+}
+
+/// Whether the container of `octx` can store the codec `id`.
+fn supports_codec(octx: &Output, id: codec::Id) -> bool {
+    // negative if it isn't known, then copying is worth a try
+    unsafe {
+        ffmpeg::ffi::avformat_query_codec(
+            octx.format().as_ptr(),
+            id.into(),
+            codec::Compliance::Normal.into(),
+        ) != 0
+    }
+}
+
+/// The audio stream of the output, copied or re-encoded from an input stream.
+struct AudioTrack {
+    /// Index of the input stream.
+    stream: usize,
+    time_base: Rational,
+    /// Start time of the input (or of the trimmed range) in `time_base` units, the video starts
+    /// at `0` (like the frames passed to `video::play`).
+    start: i64,
+    /// End of the trimmed range relative to `start`.
+    end: Option<i64>,
+    /// Index of the output stream.
+    out: usize,
+    out_time_base: Rational,
+    /// `None` if the packets are copied.
+    transcoder: Option<Transcoder>,
+}
+
+impl AudioTrack {
+    fn new(
+        stream: &format::stream::Stream,
+        start_micros: i64,
+        octx: &mut Output,
+        opath: &str,
+        mode: AudioMode,
+        range: &TimeRange,
+    ) -> Result<Self, ffmpeg::Error> {
+        let copy = match mode {
+            AudioMode::Copy => true,
+            AudioMode::Reencode => false,
+            AudioMode::Auto => range.is_full() && supports_codec(octx, stream.parameters().id()),
+        };
+        let (out, transcoder) = if copy {
+            let mut ost = octx.add_stream(encoder::find(codec::Id::None))?;
+            ost.set_parameters(stream.parameters());
+            unsafe {
+                (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+            }
+            (ost.index(), None)
+        } else {
+            let (out, transcoder) = Transcoder::new(stream, octx, opath, range)?;
+            (out, Some(transcoder))
+        };
+        Ok(Self {
+            stream: stream.index(),
+            time_base: stream.time_base(),
+            start: start_micros.rescale(ffmpeg::rescale::TIME_BASE, stream.time_base()),
+            end: range
+                .length_micros()
+                .map(|end| end.rescale(ffmpeg::rescale::TIME_BASE, stream.time_base())),
+            out,
+            out_time_base: Rational(0, 1),
+            transcoder,
+        })
+    }
+
+    /// Reads the next packet of the stream, with timestamps relative to the start of the input,
+    /// `None` after the end of the range.
+    fn next_packet(&self, ictx: &mut Input) -> Result<Option<Packet>, ffmpeg::Error> {
+        loop {
+            let Some(mut packet) = next_packet(ictx, self.stream)? else {
+                return Ok(None);
+            };
+            packet.set_pts(packet.pts().map(|pts| pts - self.start));
+            packet.set_dts(packet.dts().map(|dts| dts - self.start));
+            let pts = packet.pts().or(packet.dts()).unwrap_or(0);
+            if self.end.is_some_and(|end| pts >= end) {
+                return Ok(None);
+            }
+            // copied packets before the start are dropped, decoded ones are trimmed by the filter
+            if self.transcoder.is_none() && pts + packet.duration() <= 0 {
+                continue;
+            }
+            return Ok(Some(packet));
+        }
+    }
+
+    /// Writes (or decodes) an input `packet`.
+    fn write(&mut self, mut packet: Packet, octx: &mut Output) -> Result<(), ffmpeg::Error> {
+        match &mut self.transcoder {
+            Some(transcoder) => {
+                transcoder.decoder.send_packet(&packet)?;
+                transcoder.receive_frames(self.time_base, self.out, self.out_time_base, octx)
+            }
+            None => {
+                packet.rescale_ts(self.time_base, self.out_time_base);
+                packet.set_position(-1);
+                packet.set_stream(self.out);
+                packet.write_interleaved(octx)
+            }
+        }
+    }
+
+    /// Flushes the decoder, filter and encoder of a re-encoded stream.
+    fn finish(&mut self, octx: &mut Output) -> Result<(), ffmpeg::Error> {
+        let Some(transcoder) = &mut self.transcoder else {
+            return Ok(());
+        };
+        transcoder.decoder.send_eof()?;
+        transcoder.receive_frames(self.time_base, self.out, self.out_time_base, octx)?;
+        transcoder.filter.get("in").unwrap().source().flush()?;
+        transcoder.filter_frames(self.out, self.out_time_base, octx)?;
+        transcoder.encoder.send_eof()?;
+        transcoder.receive_packets(self.out, self.out_time_base, octx)
+    }
+}
+
+/// Decodes audio and encodes it with the default audio codec of the output container.
+struct Transcoder {
+    decoder: codec::decoder::Audio,
+    /// Converts the decoded frames to the sample format, layout and frame size of the encoder.
+    filter: filter::Graph,
+    encoder: codec::encoder::Audio,
+    /// Time base of the filtered frames and encoded packets (`1 / sample rate`).
+    time_base: Rational,
+}
+
+impl Transcoder {
+    /// Adds the output stream for the re-encoded `stream`, returns its index and the transcoder.
+    fn new(
+        stream: &format::stream::Stream,
+        octx: &mut Output,
+        opath: &str,
+        range: &TimeRange,
+    ) -> Result<(usize, Self), ffmpeg::Error> {
+        let decoder = codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .audio()?;
+        let codec = encoder::find(octx.format().codec(opath, media::Type::Audio))
+            .ok_or(ffmpeg::Error::EncoderNotFound)?
+            .audio()?;
+        let global_header = octx
+            .format()
+            .flags()
+            .contains(format::flag::Flags::GLOBAL_HEADER);
+
+        let layout = match decoder.channel_layout() {
+            l if l.is_empty() => ChannelLayout::default(decoder.channels() as i32),
+            l => l,
+        };
+        let channel_layout = codec
+            .channel_layouts()
+            .map(|layouts| layouts.best(layout.channels()))
+            .unwrap_or(ChannelLayout::STEREO);
+        let sample_format = codec
+            .formats()
+            .and_then(|mut formats| formats.next())
+            .ok_or(ffmpeg::Error::InvalidData)?;
+        let time_base = Rational(1, decoder.rate() as i32);
+
+        let mut ost = octx.add_stream(codec)?;
+        let mut encoder = codec::context::Context::from_parameters(ost.parameters())?
+            .encoder()
+            .audio()?;
+        if global_header {
+            encoder.set_flags(codec::flag::Flags::GLOBAL_HEADER);
+        }
+        encoder.set_rate(decoder.rate() as i32);
+        encoder.set_channel_layout(channel_layout);
+        encoder.set_format(sample_format);
+        encoder.set_bit_rate(decoder.bit_rate());
+        encoder.set_max_bit_rate(decoder.max_bit_rate());
+        encoder.set_time_base(time_base);
+        ost.set_time_base(time_base);
+
+        let encoder = encoder.open_as(codec)?;
+        ost.set_parameters(&encoder);
+
+        let mut filter = filter::Graph::new();
+        let args = format!(
+            "time_base={time_base}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            decoder.rate(),
+            decoder.format().name(),
+            layout.bits()
+        );
+        filter.add(&filter::find("abuffer").unwrap(), "in", &args)?;
+        filter.add(&filter::find("abuffersink").unwrap(), "out", "")?;
+        {
+            let mut out = filter.get("out").unwrap();
+            out.set_sample_format(encoder.format());
+            out.set_channel_layout(encoder.channel_layout());
+            out.set_sample_rate(encoder.rate());
+        }
+        // the timestamps are relative to the start of the range
+        let spec = match (range.is_full(), range.length_micros()) {
+            (true, _) => "anull".to_string(),
+            (false, Some(end)) => format!("atrim=start=0:end={}", end as f64 / 1_000_000.0),
+            (false, None) => "atrim=start=0".to_string(),
+        };
+        filter.output("in", 0)?.input("out", 0)?.parse(&spec)?;
+        filter.validate()?;
+        if !codec
+            .capabilities()
+            .contains(codec::Capabilities::VARIABLE_FRAME_SIZE)
+        {
+            filter
+                .get("out")
+                .unwrap()
+                .sink()
+                .set_frame_size(encoder.frame_size());
+        }
+
+        Ok((
+            ost.index(),
+            Self {
+                decoder,
+                filter,
+                encoder,
+                time_base,
+            },
+        ))
+    }
+
+    /// Passes the decoded frames (with timestamps in `stream_time_base`) through the filter.
+    fn receive_frames(
+        &mut self,
+        stream_time_base: Rational,
+        out: usize,
+        out_time_base: Rational,
+        octx: &mut Output,
+    ) -> Result<(), ffmpeg::Error> {
+        let mut decoded = ffmpeg::frame::Audio::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let pts = decoded
+                .timestamp()
+                .map(|ts| ts.rescale(stream_time_base, self.time_base));
+            decoded.set_pts(pts);
+            self.filter.get("in").unwrap().source().add(&decoded)?;
+            self.filter_frames(out, out_time_base, octx)?;
+        }
+        Ok(())
+    }
+
+    fn filter_frames(
+        &mut self,
+        out: usize,
+        out_time_base: Rational,
+        octx: &mut Output,
+    ) -> Result<(), ffmpeg::Error> {
+        let mut filtered = ffmpeg::frame::Audio::empty();
+        while self
+            .filter
+            .get("out")
+            .unwrap()
+            .sink()
+            .frame(&mut filtered)
+            .is_ok()
+        {
+            self.encoder.send_frame(&filtered)?;
+            self.receive_packets(out, out_time_base, octx)?;
+        }
+        Ok(())
+    }
+
+    fn receive_packets(
+        &mut self,
+        out: usize,
+        out_time_base: Rational,
+        octx: &mut Output,
+    ) -> Result<(), ffmpeg::Error> {
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            encoded.set_stream(out);
+            encoded.rescale_ts(self.time_base, out_time_base);
+            encoded.write_interleaved(octx)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{TempPath, tone_wav, video};
+
+    use std::time::Duration;
+
+    /// Timestamp of the first decoded sample and the length of the audio of `path` in seconds.
+    fn audio_span(path: &str) -> (f64, f64) {
+        let mut ictx = format::input(&path).unwrap();
+        let stream = ictx.streams().best(media::Type::Audio).unwrap();
+        let (index, time_base) = (stream.index(), stream.time_base());
+        let mut decoder = codec::context::Context::from_parameters(stream.parameters())
+            .unwrap()
+            .decoder();
+        // so the priming samples of the encoder are skipped with the right timestamps
+        decoder.set_packet_time_base(time_base);
+        let mut decoder = decoder.audio().unwrap();
+        let mut decoded = ffmpeg::frame::Audio::empty();
+        let (mut start, mut samples) = (None, 0);
+        let mut receive = |decoder: &mut codec::decoder::Audio| {
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                start.get_or_insert(decoded.timestamp().unwrap());
+                samples += decoded.samples();
+            }
+        };
+        for (stream, packet) in ictx.packets() {
+            if stream.index() == index {
+                decoder.send_packet(&packet).unwrap();
+                receive(&mut decoder);
+            }
+        }
+        decoder.send_eof().unwrap();
+        receive(&mut decoder);
+        (
+            start.unwrap() as f64 * f64::from(time_base),
+            samples as f64 / decoder.rate() as f64,
+        )
+    }
+
+    fn assert_streams(path: &str, audio_codec: Option<codec::Id>) {
+        let ictx = format::input(&path).unwrap();
+        assert_eq!(ictx.streams().count(), 2);
+        assert!(ictx.streams().best(media::Type::Video).is_some());
+        let audio = ictx.streams().best(media::Type::Audio).unwrap();
+        if let Some(id) = audio_codec {
+            assert_eq!(audio.parameters().id(), id);
+        }
+    }
+
+    /// Muxes the `video` with a `secs` long tone into a new file with the `extension`.
+    fn mux(
+        video: &TempPath,
+        secs: u32,
+        extension: &str,
+        mode: AudioMode,
+        range: &TimeRange,
+    ) -> TempPath {
+        let wav = TempPath::new("tone.wav");
+        tone_wav(wav.path(), secs);
+        let output = TempPath::new(&format!("muxed.{extension}"));
+        let best = &StreamSelector::Best;
+        convert_with(
+            wav.as_str(),
+            video.as_str(),
+            output.as_str(),
+            mode,
+            range,
+            best,
+        )
+        .unwrap();
+        output
+    }
+
+    #[test]
+    fn copies_the_audio() {
+        let output = mux(&video(25), 1, "mkv", AudioMode::Copy, &TimeRange::default());
+        assert_streams(output.as_str(), Some(codec::Id::PCM_S16LE));
+        assert_eq!(audio_span(output.as_str()), (0.0, 1.0));
+    }
+
+    #[test]
+    fn copied_audio_is_cut_between_packets() {
+        let range = TimeRange::with_duration(Duration::from_millis(500), Duration::from_secs(1));
+        let output = mux(&video(25), 2, "mkv", AudioMode::Copy, &range);
+        assert_streams(output.as_str(), Some(codec::Id::PCM_S16LE));
+        let (start, len) = audio_span(output.as_str());
+        // a packet of the wav demuxer is shorter than 50ms
+        assert!(start.abs() < 0.05, "starts at {start}");
+        assert!((start + len - 1.0).abs() < 0.05, "ends at {}", start + len);
+    }
+
+    #[test]
+    fn transcodes_the_trimmed_range() {
+        let range = TimeRange::with_duration(Duration::from_millis(500), Duration::from_secs(1));
+        let output = mux(&video(25), 2, "mp4", AudioMode::Reencode, &range);
+        assert_streams(output.as_str(), None);
+        let (start, len) = audio_span(output.as_str());
+        // the trim is exact, but the encoder works in frames of 1024 samples (aac)
+        let frame = 1024.0 / 48_000.0;
+        assert!(start.abs() < frame, "starts at {start}");
+        assert!((len - 1.0).abs() < frame, "{len} long");
+    }
+
+    #[test]
+    fn missing_audio_stream() {
+        let video = video(5);
+        let output = TempPath::new("missing.mkv");
+        let (mode, range) = (AudioMode::Auto, &TimeRange::default());
+        let result = convert_with(
+            video.as_str(),
+            video.as_str(),
+            output.as_str(),
+            mode,
+            range,
+            &StreamSelector::Index(3),
+        );
+        assert_eq!(result, Err(ffmpeg::Error::StreamNotFound));
+
+        // the best stream may not exist, then there is no audio
+        let best = &StreamSelector::Best;
+        convert_with(
+            video.as_str(),
+            video.as_str(),
+            output.as_str(),
+            mode,
+            range,
+            best,
+        )
+        .unwrap();
+        let ictx = format::input(output.path()).unwrap();
+        assert_eq!(ictx.streams().count(), 1);
     }
 }
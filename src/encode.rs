@@ -0,0 +1,565 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling::{self, flag::Flags};
+use ffmpeg::util::frame::Video;
+use ffmpeg::{Dictionary, Packet, Rational, Rescale, codec, encoder, format};
+
+use imageproc::image::imageops::{self, FilterType};
+use imageproc::image::RgbImage;
+use openh264::OpenH264API;
+use openh264::encoder::{BitRate, Encoder, EncoderConfig, FrameRate, IntraFramePeriod};
+use openh264::formats::{RgbSliceU8, YUVBuffer};
+
+use std::fs::File;
+use std::path::Path;
+
+pub use openh264::encoder::{Profile, RateControlMode};
+
+/// Codec of the output video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    H265,
+    Vp9,
+    Av1,
+    ProRes,
+    /// Animated PNG.
+    Apng,
+    /// Animated WebP.
+    WebP,
+}
+
+impl VideoCodec {
+    /// Names of the ffmpeg encoders of the codec, the first one that is compiled in is used.
+    pub fn encoder_names(self) -> &'static [&'static str] {
+        match self {
+            Self::H264 => &["libx264"],
+            Self::H265 => &["libx265"],
+            Self::Vp9 => &["libvpx-vp9"],
+            Self::Av1 => &["libaom-av1", "libsvtav1"],
+            Self::ProRes => &["prores_ks", "prores"],
+            Self::Apng => &["apng"],
+            Self::WebP => &["libwebp_anim", "libwebp"],
+        }
+    }
+
+    /// The pixel format used if the encoder supports it.
+    fn pixel_format(self) -> Pixel {
+        match self {
+            Self::ProRes => Pixel::YUV422P10LE,
+            Self::Apng => Pixel::RGB24,
+            _ => Pixel::YUV420P,
+        }
+    }
+
+    /// Whether openh264 can stand in if there is no ffmpeg encoder.
+    fn is_video(self) -> bool {
+        !matches!(self, Self::Apng | Self::WebP)
+    }
+
+    /// Name of the muxer, if it can't be guessed from the extension (`.png` is guessed as a
+    /// single image).
+    fn muxer_name(self) -> Option<&'static str> {
+        match self {
+            Self::Apng => Some("apng"),
+            _ => None,
+        }
+    }
+
+    /// Options of the muxer.
+    fn muxer_options(self) -> Dictionary<'static> {
+        let mut options = Dictionary::new();
+        if self == Self::Apng {
+            // loop forever, like GIFs
+            options.set("plays", "0");
+        }
+        options
+    }
+}
+
+/// Options of the video encoder used by `video::draw_to_file`.
+#[derive(Debug, Clone)]
+pub struct EncoderOptions {
+    pub codec: VideoCodec,
+    /// Target bitrate in bits per second.
+    pub bitrate: u32,
+    /// Constant rate factor of the ffmpeg encoders (lower is better), replaces `bitrate`.
+    pub crf: Option<u32>,
+    /// Speed preset of the ffmpeg encoders (e.g. `"veryfast"` for libx264 and libx265).
+    pub preset: Option<String>,
+    /// Rate control of openh264.
+    pub rate_control: RateControlMode,
+    /// Number of frames from one keyframe to the next (`0` lets the encoder decide).
+    pub keyframe_interval: u32,
+    /// H.264 profile of openh264, `None` lets the encoder decide.
+    pub profile: Option<Profile>,
+    /// Fixed size of the output video (e.g. `Some((1920, 1080))`), the rendered frames are scaled
+    /// to fit and letterboxed. `None` uses the size of the rendered frames.
+    pub resolution: Option<(u32, u32)>,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        Self {
+            codec: VideoCodec::default(),
+            bitrate: 2_000_000,
+            crf: None,
+            preset: None,
+            rate_control: RateControlMode::Bitrate,
+            keyframe_interval: 250,
+            profile: None,
+            resolution: None,
+        }
+    }
+}
+
+/// The first compiled in ffmpeg encoder of `codec`.
+pub fn find_encoder(codec: VideoCodec) -> Option<ffmpeg::Codec> {
+    codec
+        .encoder_names()
+        .iter()
+        .find_map(|name| encoder::find_by_name(name))
+}
+
+/// Extension of the file a `VideoWriter` writes for `dst`: the extension of `dst` (the container
+/// is guessed from it), or `mp4` if openh264 is used.
+pub fn output_extension<'a>(dst: &'a str, options: &EncoderOptions) -> &'a str {
+    match find_encoder(options.codec) {
+        Some(_) => Path::new(dst)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("mp4"),
+        None => "mp4",
+    }
+}
+
+/// Encodes frames of packed `RGB` data into a video file.
+pub enum VideoWriter {
+    FFmpeg(FFmpegEncoder),
+    /// Fallback if there is no ffmpeg encoder for the codec.
+    OpenH264(OpenH264Writer),
+}
+
+impl VideoWriter {
+    /// Creates the video file at `path` for frames of `width` x `height` (both even), with
+    /// timestamps in `time_base` units.
+    pub fn new(
+        path: &str,
+        width: u32,
+        height: u32,
+        frame_rate: Rational,
+        time_base: Rational,
+        options: &EncoderOptions,
+    ) -> Result<Self, ffmpeg::Error> {
+        if let Some(codec) = find_encoder(options.codec) {
+            let encoder =
+                FFmpegEncoder::new(path, codec, width, height, frame_rate, time_base, options)?;
+            return Ok(Self::FFmpeg(encoder));
+        }
+        if !options.codec.is_video() {
+            return Err(ffmpeg::Error::EncoderNotFound);
+        }
+        if options.codec != VideoCodec::H264 {
+            eprintln!(
+                "No ffmpeg encoder for {:?} ({}), falling back to openh264",
+                options.codec,
+                options.codec.encoder_names().join(", ")
+            );
+        }
+        Ok(Self::OpenH264(OpenH264Writer::new(
+            path, width, height, frame_rate, time_base, options,
+        )))
+    }
+
+    /// Encodes and writes the next frame, `pts` is its presentation timestamp.
+    pub fn write(
+        &mut self,
+        rgb: &[u8],
+        width: usize,
+        height: usize,
+        pts: i64,
+    ) -> Result<(), ffmpeg::Error> {
+        match self {
+            Self::FFmpeg(encoder) => encoder.encode(rgb, pts),
+            Self::OpenH264(writer) => {
+                writer.write(rgb, width, height, pts);
+                Ok(())
+            }
+        }
+    }
+
+    /// Flushes the encoder and finishes the file.
+    pub fn finish(self) -> Result<(), ffmpeg::Error> {
+        match self {
+            Self::FFmpeg(encoder) => encoder.finish(),
+            Self::OpenH264(writer) => {
+                writer.finish();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Time base of the MP4 files written by minimp4.
+const MP4_TIME_BASE: Rational = Rational(1, 90_000);
+
+/// Encodes frames with openh264 and writes them into an MP4 file with minimp4.
+pub struct OpenH264Writer {
+    encoder: H264Encoder,
+    muxer: minimp4::Mp4Muxer<File>,
+    frame_rate: Rational,
+    time_base: Rational,
+    /// The last encoded frame, it's written once its duration is known.
+    pending: Option<Vec<u8>>,
+    /// Duration of the written frames in ticks of `MP4_TIME_BASE`.
+    written: u64,
+}
+
+impl OpenH264Writer {
+    pub fn new(
+        path: &str,
+        width: u32,
+        height: u32,
+        frame_rate: Rational,
+        time_base: Rational,
+        options: &EncoderOptions,
+    ) -> Self {
+        let file = File::create(path).expect("Couldn't create video file");
+        let mut muxer = minimp4::Mp4Muxer::new(file);
+        muxer.init_video(width as i32, height as i32, false, path);
+        Self {
+            encoder: H264Encoder::new(options, f64::from(frame_rate) as f32),
+            muxer,
+            frame_rate,
+            time_base,
+            pending: None,
+            written: 0,
+        }
+    }
+
+    /// Encodes the next frame and writes the previous one, which lasts until `pts`.
+    pub fn write(&mut self, rgb: &[u8], width: usize, height: usize, pts: i64) {
+        let buf = self.encoder.encode(rgb, width, height);
+        if let Some(previous) = self.pending.replace(buf) {
+            let end = pts.rescale(self.time_base, MP4_TIME_BASE).max(0) as u64;
+            let fps = mp4_fps(&mut self.written, end);
+            self.muxer.write_video_with_fps(&previous, fps);
+        }
+    }
+
+    /// Writes the last frame (with the average duration) and finishes the file.
+    pub fn finish(mut self) {
+        if let Some(last) = self.pending.take() {
+            let duration = 1i64.rescale(self.frame_rate.invert(), MP4_TIME_BASE) as u64;
+            let fps = mp4_fps(&mut self.written, self.written + duration);
+            self.muxer.write_video_with_fps(&last, fps);
+        }
+        self.muxer.close();
+    }
+}
+
+/// The frame rate for the next frame of minimp4 that ends as close to `end` as possible.
+///
+/// minimp4 only takes a frame rate per frame (the frame lasts `90000 / fps` ticks), so the
+/// durations can't be exact, but the rounding errors don't add up.
+fn mp4_fps(written: &mut u64, end: u64) -> u32 {
+    let duration = end.saturating_sub(*written).clamp(1, 90_000);
+    let fps = ((90_000 + duration / 2) / duration).max(1);
+    *written += 90_000 / fps;
+    fps as u32
+}
+
+/// Encodes frames of packed `RGB` data with an ffmpeg encoder into the container guessed from
+/// the path.
+pub struct FFmpegEncoder {
+    octx: format::context::Output,
+    encoder: encoder::Video,
+    scaler: scaling::Context,
+    /// The `RGB24` frame the data is copied into before it's converted.
+    rgb: Video,
+    time_base: Rational,
+    stream_time_base: Rational,
+    /// Timestamp of the last frame.
+    pts: Option<i64>,
+}
+
+impl FFmpegEncoder {
+    pub fn new(
+        path: &str,
+        codec: ffmpeg::Codec,
+        width: u32,
+        height: u32,
+        frame_rate: Rational,
+        time_base: Rational,
+        options: &EncoderOptions,
+    ) -> Result<Self, ffmpeg::Error> {
+        let mut octx = match options.codec.muxer_name() {
+            Some(name) => format::output_as(&path, name)?,
+            None => format::output(&path)?,
+        };
+        let global_header = octx
+            .format()
+            .flags()
+            .contains(format::flag::Flags::GLOBAL_HEADER);
+
+        let preferred = options.codec.pixel_format();
+        let pixel_format = match codec.video()?.formats() {
+            Some(formats) => {
+                let formats: Vec<Pixel> = formats.collect();
+                match formats.first() {
+                    Some(&first) if !formats.contains(&preferred) => first,
+                    _ => preferred,
+                }
+            }
+            None => preferred,
+        };
+        let mut ost = octx.add_stream(codec)?;
+        let mut encoder = codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .video()?;
+        encoder.set_width(width);
+        encoder.set_height(height);
+        encoder.set_format(pixel_format);
+        encoder.set_time_base(time_base);
+        encoder.set_frame_rate(Some(frame_rate));
+        if options.keyframe_interval > 0 {
+            encoder.set_gop(options.keyframe_interval);
+        }
+        if global_header {
+            encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let mut dictionary = Dictionary::new();
+        match options.crf {
+            Some(crf) => {
+                // libvpx and libaom only use the crf if the bitrate is 0
+                encoder.set_bit_rate(0);
+                dictionary.set("crf", &crf.to_string());
+            }
+            None => encoder.set_bit_rate(options.bitrate as usize),
+        }
+        if let Some(preset) = &options.preset {
+            dictionary.set("preset", preset);
+        }
+        let encoder = encoder.open_with(dictionary)?;
+        ost.set_parameters(&encoder);
+        ost.set_time_base(time_base);
+
+        octx.write_header_with(options.codec.muxer_options())?;
+        let stream_time_base = octx.stream(0).expect("stream was added").time_base();
+
+        let scaler = scaling::Context::get(
+            Pixel::RGB24,
+            width,
+            height,
+            pixel_format,
+            width,
+            height,
+            Flags::BILINEAR,
+        )?;
+
+        Ok(Self {
+            octx,
+            encoder,
+            scaler,
+            rgb: Video::new(Pixel::RGB24, width, height),
+            time_base,
+            stream_time_base,
+            pts: None,
+        })
+    }
+
+    /// Encodes a frame of packed `RGB` data with the size the encoder was created with.
+    pub fn encode(&mut self, rgb: &[u8], pts: i64) -> Result<(), ffmpeg::Error> {
+        let row = self.rgb.width() as usize * 3;
+        let stride = self.rgb.stride(0);
+        let data = self.rgb.data_mut(0);
+        for (y, src) in rgb.chunks_exact(row).enumerate() {
+            data[y * stride..y * stride + row].copy_from_slice(src);
+        }
+
+        let mut frame = Video::empty();
+        self.scaler.run(&self.rgb, &mut frame)?;
+        // encoders reject timestamps that don't increase
+        let pts = match self.pts {
+            Some(last) if pts <= last => last + 1,
+            _ => pts,
+        };
+        frame.set_pts(Some(pts));
+        self.pts = Some(pts);
+
+        self.encoder.send_frame(&frame)?;
+        self.write_packets()
+    }
+
+    /// Flushes the encoder and writes the trailer of the container.
+    pub fn finish(mut self) -> Result<(), ffmpeg::Error> {
+        self.encoder.send_eof()?;
+        self.write_packets()?;
+        self.octx.write_trailer()
+    }
+
+    fn write_packets(&mut self) -> Result<(), ffmpeg::Error> {
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            packet.rescale_ts(self.time_base, self.stream_time_base);
+            packet.write_interleaved(&mut self.octx)?;
+        }
+        Ok(())
+    }
+}
+
+/// H.264 encoder for all frames of one output, so only every `keyframe_interval` frame is a
+/// keyframe.
+pub struct H264Encoder {
+    encoder: Encoder,
+}
+
+impl H264Encoder {
+    pub fn new(options: &EncoderOptions, frame_rate: f32) -> Self {
+        let mut config = EncoderConfig::new()
+            .bitrate(BitRate::from_bps(options.bitrate))
+            .rate_control_mode(options.rate_control)
+            .intra_frame_period(IntraFramePeriod::from_num_frames(options.keyframe_interval))
+            .max_frame_rate(FrameRate::from_hz(frame_rate))
+            // every frame has to be written, otherwise the muxer gets out of sync
+            .skip_frames(false);
+        if let Some(profile) = options.profile {
+            config = config.profile(profile);
+        }
+        let encoder = Encoder::with_api_config(OpenH264API::from_source(), config)
+            .expect("Couldn't create encoder");
+        Self { encoder }
+    }
+
+    /// Encodes a frame of packed `RGB` data and returns the resulting NAL units (Annex B).
+    pub fn encode(&mut self, rgb: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let rgb_source = RgbSliceU8::new(rgb, (width, height));
+        let yuv = YUVBuffer::from_rgb_source(rgb_source);
+
+        let bitstream = self.encoder.encode(&yuv).expect("Couldn't encode frame");
+
+        let mut buf = Vec::new();
+        bitstream.write_vec(&mut buf);
+        buf
+    }
+}
+
+/// Size of the output video for rendered frames of the given size.
+///
+/// H.264 requires even dimensions, so odd ones are rounded up (and the frames padded).
+pub fn output_size(width: u32, height: u32, resolution: Option<(u32, u32)>) -> (u32, u32) {
+    let (width, height) = resolution.unwrap_or((width, height));
+    (width + width % 2, height + height % 2)
+}
+
+/// Pads the `image` with black on the right and bottom to `width` x `height`.
+pub fn pad(image: RgbImage, width: u32, height: u32) -> RgbImage {
+    if image.dimensions() == (width, height) {
+        return image;
+    }
+    let mut padded = RgbImage::new(width, height);
+    imageops::replace(&mut padded, &image, 0, 0);
+    padded
+}
+
+/// Fits the `image` into `width` x `height`, it is scaled to fit (keeping the aspect ratio),
+/// centered and the rest is filled with black.
+pub fn letterbox(image: RgbImage, width: u32, height: u32) -> RgbImage {
+    if image.dimensions() == (width, height) {
+        return image;
+    }
+    let factor = (width as f64 / image.width() as f64).min(height as f64 / image.height() as f64);
+    let scaled_width = ((image.width() as f64 * factor).round() as u32).clamp(1, width);
+    let scaled_height = ((image.height() as f64 * factor).round() as u32).clamp(1, height);
+    let image = if (scaled_width, scaled_height) == image.dimensions() {
+        image
+    } else {
+        imageops::resize(&image, scaled_width, scaled_height, FilterType::Triangle)
+    };
+    let mut boxed = RgbImage::new(width, height);
+    imageops::replace(
+        &mut boxed,
+        &image,
+        ((width - scaled_width) / 2) as i64,
+        ((height - scaled_height) / 2) as i64,
+    );
+    boxed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::TempPath;
+
+    use imageproc::image::Rgb;
+
+    #[test]
+    fn even_output_size() {
+        assert_eq!(output_size(1001, 480, None), (1002, 480));
+        assert_eq!(output_size(1001, 481, Some((1920, 1080))), (1920, 1080));
+    }
+
+    #[test]
+    fn pad_odd_size() {
+        let image = RgbImage::from_pixel(3, 3, Rgb([255, 255, 255]));
+        let padded = pad(image, 4, 4);
+        assert_eq!(padded.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(padded.get_pixel(3, 3).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn letterbox_wide_image() {
+        let image = RgbImage::from_pixel(400, 100, Rgb([255, 255, 255]));
+        let boxed = letterbox(image, 200, 200);
+        assert_eq!(boxed.dimensions(), (200, 200));
+        // scaled to 200 x 50 and centered vertically
+        assert_eq!(boxed.get_pixel(100, 74).0, [0, 0, 0]);
+        assert_eq!(boxed.get_pixel(100, 75).0, [255, 255, 255]);
+        assert_eq!(boxed.get_pixel(100, 124).0, [255, 255, 255]);
+        assert_eq!(boxed.get_pixel(100, 125).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn mp4_durations_follow_timestamps() {
+        // 29.97 fps, a frame lasts 3003 ticks
+        let mut written = 0;
+        for frame in 1..=1000 {
+            mp4_fps(&mut written, frame * 3003);
+            assert!(written.abs_diff(frame * 3003) < 100, "frame {frame}");
+        }
+        // variable frame rate
+        let mut written = 0;
+        for end in [3000, 4500, 9000, 9750, 12000] {
+            mp4_fps(&mut written, end);
+            assert!(written.abs_diff(end) < 100, "{written} {end}");
+        }
+    }
+
+    #[test]
+    fn write_video() {
+        ffmpeg::init().unwrap();
+        let options = EncoderOptions::default();
+        let file = TempPath::new(&format!(
+            "write-video.{}",
+            output_extension("out.mkv", &options)
+        ));
+        let path = file.as_str();
+
+        let mut writer =
+            VideoWriter::new(path, 64, 48, Rational(24, 1), Rational(1, 24), &options).unwrap();
+        let image = RgbImage::from_pixel(64, 48, Rgb([255, 128, 0]));
+        for pts in 0..24 {
+            writer.write(image.as_raw(), 64, 48, pts).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let ictx = format::input(&path).unwrap();
+        let stream = ictx.streams().best(ffmpeg::media::Type::Video).unwrap();
+        let parameters = stream.parameters();
+        assert_eq!(parameters.id(), codec::Id::H264);
+    }
+}
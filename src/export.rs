@@ -0,0 +1,476 @@
+use crate::image::FontMetrics;
+use crate::{CHARS, Frame, Grid, subset};
+
+use ab_glyph::{Font, FontRef};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes the symbols of the `grid` without any colors.
+///
+/// Like `crate::draw` every cell is written twice, because characters are about twice as high as
+/// they are wide (this is true for all text exports).
+pub fn write_text(grid: &Grid, out: &mut impl Write) -> io::Result<()> {
+    for row in grid.rows() {
+        let line: String = row.iter().flat_map(|c| [c.symbol, c.symbol]).collect();
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Writes the `grid` with ANSI escape codes (true color), so it can be `cat`ed to a terminal.
+pub fn write_ansi(grid: &Grid, out: &mut impl Write) -> io::Result<()> {
+    for row in grid.rows() {
+        let mut fg = None;
+        let mut bg = None;
+        for cell in row {
+            if bg != Some(cell.bg) {
+                let (r, g, b) = cell.bg;
+                write!(out, "\x1b[48;2;{r};{g};{b}m")?;
+                bg = Some(cell.bg);
+            }
+            if fg != Some(cell.fg) {
+                let (r, g, b) = cell.fg;
+                write!(out, "\x1b[38;2;{r};{g};{b}m")?;
+                fg = Some(cell.fg);
+            }
+            write!(out, "{0}{0}", cell.symbol)?;
+        }
+        // reset at the end of every line, so the colors don't leak into the next line
+        writeln!(out, "\x1b[0m")?;
+    }
+    Ok(())
+}
+
+/// Writes the `grid` as JSON.
+///
+/// ```json
+/// { "width": 1, "height": 1, "cells": [[{ "char": "@", "fg": [255, 255, 255], "bg": [0, 0, 0] }]] }
+/// ```
+pub fn write_json(grid: &Grid, out: &mut impl Write) -> io::Result<()> {
+    write!(
+        out,
+        "{{\"width\":{},\"height\":{},\"cells\":[",
+        grid.width, grid.height
+    )?;
+    for (y, row) in grid.rows().enumerate() {
+        if y > 0 {
+            write!(out, ",")?;
+        }
+        write!(out, "[")?;
+        for (x, cell) in row.iter().enumerate() {
+            if x > 0 {
+                write!(out, ",")?;
+            }
+            let (r, g, b) = cell.fg;
+            let (br, bg, bb) = cell.bg;
+            write!(
+                out,
+                "{{\"char\":{},\"fg\":[{r},{g},{b}],\"bg\":[{br},{bg},{bb}]}}",
+                json_string(cell.symbol)
+            )?;
+        }
+        write!(out, "]")?;
+    }
+    writeln!(out, "]}}")
+}
+
+/// Options of the HTML export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlOptions {
+    /// CSS `font-family` of the text (like `"Fira Code", monospace`).
+    pub font_family: String,
+    /// Font size in pixels.
+    pub font_size: f32,
+    /// Background of the whole page (the background of the cells is ignored).
+    pub background: (u8, u8, u8),
+    /// Use a CSS class per color instead of inline styles (smaller output for art with only a few
+    /// colors).
+    pub css_classes: bool,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        Self {
+            font_family: "monospace".to_string(),
+            font_size: 12.0,
+            background: crate::grid::BACKGROUND,
+            css_classes: false,
+        }
+    }
+}
+
+/// Writes the `grid` as a self-contained HTML page, a `<pre>` with a `<span>` for every run of
+/// cells with the same color.
+///
+/// Fails without writing anything if the `font_family` of the `options` can't be used in a style
+/// sheet.
+pub fn write_html(grid: &Grid, options: &HtmlOptions, out: &mut impl Write) -> io::Result<()> {
+    let font_family = css_font_family(&options.font_family)?;
+    let mut classes = HashMap::new();
+    if options.css_classes {
+        for cell in &grid.cells {
+            let next = classes.len();
+            classes.entry(cell.fg).or_insert(next);
+        }
+    }
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<style>")?;
+    writeln!(
+        out,
+        "body {{ margin: 0; background: {}; }}",
+        hex(options.background)
+    )?;
+    writeln!(
+        out,
+        "pre {{ margin: 0; font-family: {font_family}; font-size: {}px; line-height: 1; }}",
+        options.font_size
+    )?;
+    let mut sorted: Vec<_> = classes.iter().collect();
+    sorted.sort_by_key(|(_, i)| **i);
+    for (color, i) in sorted {
+        writeln!(out, ".c{i} {{ color: {}; }}", hex(*color))?;
+    }
+    writeln!(out, "</style>")?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    write!(out, "<pre>")?;
+    for row in grid.rows() {
+        for run in row.chunk_by(|a, b| a.fg == b.fg) {
+            let text: String = run.iter().flat_map(|c| [c.symbol, c.symbol]).collect();
+            match classes.get(&run[0].fg) {
+                Some(i) => write!(out, "<span class=\"c{i}\">")?,
+                None => write!(out, "<span style=\"color:{}\">", hex(run[0].fg))?,
+            }
+            write!(out, "{}</span>", escape_html(&text))?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "</pre>")?;
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
+
+/// Options of the SVG export.
+#[derive(Debug, Clone)]
+pub struct SvgOptions<'a> {
+    /// CSS `font-family` of the text (ignored if the font is embedded).
+    pub font_family: String,
+    /// Font size in pixels.
+    pub font_size: f32,
+    pub background: (u8, u8, u8),
+    /// Font the advance width and line height are taken from, if `None` the usual metrics of a
+    /// monospace font are assumed (an advance of `0.6em` and a line height of `1.2em`).
+    pub font: Option<&'a FontRef<'a>>,
+    /// Embed `font` as a `@font-face`, so the svg looks the same on systems without the font.
+    ///
+    /// Only the glyphs of `CHARS` and the symbols of the grid are embedded (if the font can be
+    /// subset, see `subset::subset`).
+    pub embed_font: bool,
+}
+
+impl Default for SvgOptions<'_> {
+    fn default() -> Self {
+        Self {
+            font_family: "monospace".to_string(),
+            font_size: 12.0,
+            background: crate::grid::BACKGROUND,
+            font: None,
+            embed_font: false,
+        }
+    }
+}
+
+/// Writes the `grid` as an SVG, every row is a `<text>` with a `<tspan>` for every run of cells
+/// with the same color.
+///
+/// The runs are positioned explicitly, so the columns line up even if the font is replaced.
+pub fn write_svg(grid: &Grid, options: &SvgOptions, out: &mut impl Write) -> io::Result<()> {
+    let metrics = match options.font {
+        Some(font) => FontMetrics::new(font, options.font_size),
+        None => FontMetrics {
+            advance: 0.6 * options.font_size,
+            ascent: 0.9 * options.font_size,
+            descent: -0.3 * options.font_size,
+            line_gap: 0.0,
+        },
+    };
+    let cell_width = metrics.advance * 2.0;
+    let line_height = metrics.line_height();
+    let width = grid.width as f32 * cell_width;
+    let height = grid.height as f32 * line_height;
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" xml:space=\"preserve\">",
+        num(width),
+        num(height)
+    )?;
+    writeln!(out, "<style>")?;
+    let font_family = match options.font {
+        Some(font) if options.embed_font => {
+            let chars = CHARS.iter().copied();
+            let subset = subset::subset(font, chars.chain(grid.cells.iter().map(|c| c.symbol)));
+            let data = subset.as_deref().unwrap_or(font.font_data());
+            let mime = if data.starts_with(b"OTTO") { "otf" } else { "ttf" };
+            writeln!(
+                out,
+                "@font-face {{ font-family: \"ascii\"; src: url(data:font/{mime};base64,{}); }}",
+                base64(data)
+            )?;
+            "\"ascii\"".to_string()
+        }
+        _ => escape_html(&options.font_family),
+    };
+    writeln!(
+        out,
+        "text {{ font-family: {font_family}; font-size: {}px; }}",
+        num(options.font_size)
+    )?;
+    writeln!(out, "</style>")?;
+    writeln!(
+        out,
+        "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+        hex(options.background)
+    )?;
+    for (y, row) in grid.rows().enumerate() {
+        let baseline = y as f32 * line_height + metrics.line_gap / 2.0 + metrics.ascent;
+        write!(out, "<text y=\"{}\">", num(baseline))?;
+        let mut x = 0;
+        for run in row.chunk_by(|a, b| a.fg == b.fg) {
+            if run.iter().any(|c| c.symbol != ' ') {
+                let text: String = run.iter().flat_map(|c| [c.symbol, c.symbol]).collect();
+                write!(
+                    out,
+                    "<tspan x=\"{}\" fill=\"{}\">{}</tspan>",
+                    num(x as f32 * cell_width),
+                    hex(run[0].fg),
+                    escape_html(&text)
+                )?;
+            }
+            x += run.len();
+        }
+        writeln!(out, "</text>")?;
+    }
+    writeln!(out, "</svg>")
+}
+
+/// Formats the number with at most two decimals.
+fn num(value: f32) -> String {
+    format!("{}", (value * 100.0).round() / 100.0)
+}
+
+fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(TABLE[(n >> (18 - i * 6) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// The `font_family` as it is, if it can't end the rule or the `<style>` element it's written into.
+///
+/// The text of a `<style>` element isn't unescaped in HTML, so it can't be escaped either.
+fn css_font_family(font_family: &str) -> io::Result<&str> {
+    match font_family.contains(['<', '{', '}', ';']) {
+        true => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{font_family:?} is not a CSS font family"),
+        )),
+        false => Ok(font_family),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn json_string(c: char) -> String {
+    match c {
+        '"' => "\"\\\"\"".to_string(),
+        '\\' => "\"\\\\\"".to_string(),
+        c if c.is_control() => format!("\"\\u{:04x}\"", c as u32),
+        c => format!("\"{c}\""),
+    }
+}
+
+/// Exports the `frame` to the given `target` path, the format is taken from its extension:
+///
+/// - `txt`: plain text
+/// - `ans`: text with ANSI colors
+/// - `json`: the cell grid
+/// - `html`: HTML page (with the default `HtmlOptions`)
+/// - `svg`: SVG (with the default `SvgOptions`)
+pub fn draw_to_file(target: &str, frame: &Frame) -> io::Result<()> {
+    let path = Path::new(target);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let grid = Grid::from_frame(frame);
+    let mut out = BufWriter::new(File::create(path)?);
+    match extension.to_lowercase().as_str() {
+        "txt" => write_text(&grid, &mut out)?,
+        "ans" => write_ansi(&grid, &mut out)?,
+        "json" => write_json(&grid, &mut out)?,
+        "html" | "htm" => write_html(&grid, &HtmlOptions::default(), &mut out)?,
+        "svg" => write_svg(&grid, &SvgOptions::default(), &mut out)?,
+        e => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported export format: {e:?}"),
+            ));
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> Grid {
+        let pixels = [255, 255, 255, 0, 0, 0, 255, 0, 0, 255, 0, 0];
+        Grid::from_frame(&Frame::from_packed(2, &pixels).unwrap())
+    }
+
+    #[test]
+    fn text() {
+        let mut out = Vec::new();
+        write_text(&grid(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "@@\n----\n");
+    }
+
+    #[test]
+    fn ansi() {
+        let mut out = Vec::new();
+        write_ansi(&grid(), &mut out).unwrap();
+        let ansi = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = ansi.lines().collect();
+        assert_eq!(
+            lines[1],
+            "\x1b[48;2;0;0;0m\x1b[38;2;255;0;0m----\x1b[0m",
+            "same colors are only set once"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let mut out = Vec::new();
+        write_json(&grid(), &mut out).unwrap();
+        let json = String::from_utf8(out).unwrap();
+        assert!(json.starts_with(
+            r#"{"width":2,"height":2,"cells":[[{"char":"@","fg":[255,255,255],"bg":[0,0,0]},"#
+        ));
+        assert!(json.ends_with("]]}\n"));
+    }
+
+    #[test]
+    fn html() {
+        let pixels = [255, 0, 0, 255, 0, 0, 210, 210, 210];
+        let grid = Grid::from_frame(&Frame::from_packed(3, &pixels).unwrap());
+        let mut out = Vec::new();
+        write_html(&grid, &HtmlOptions::default(), &mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains(
+            "<pre><span style=\"color:#ff0000\">----</span><span style=\"color:#d2d2d2\">&amp;&amp;</span>\n</pre>"
+        ));
+
+        let options = HtmlOptions {
+            css_classes: true,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_html(&grid, &options, &mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains(".c1 { color: #d2d2d2; }"));
+        assert!(html.contains("<span class=\"c0\">----</span>"));
+    }
+
+    #[test]
+    fn html_font_family() {
+        let html = |font_family: &str| {
+            let options = HtmlOptions {
+                font_family: font_family.to_string(),
+                ..Default::default()
+            };
+            let mut out = Vec::new();
+            write_html(&grid(), &options, &mut out).map(|()| String::from_utf8(out).unwrap())
+        };
+        let page = html("\"Fira Code\", monospace").unwrap();
+        assert!(page.contains("pre { margin: 0; font-family: \"Fira Code\", monospace; font-size"));
+
+        for family in ["a; color: red", "a } body { color: red", "</style><script>"] {
+            let err = html(family).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{family}");
+        }
+    }
+
+    #[test]
+    fn svg() {
+        let pixels = [255, 0, 0, 0, 0, 0, 210, 210, 210, 0, 0, 255];
+        let grid = Grid::from_frame(&Frame::from_packed(2, &pixels).unwrap());
+        let mut out = Vec::new();
+        write_svg(&grid, &SvgOptions::default(), &mut out).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.contains("width=\"28.8\" height=\"28.8\""));
+        // the empty black cell is skipped
+        assert!(svg.contains("<text y=\"10.8\"><tspan x=\"0\" fill=\"#ff0000\">--</tspan></text>"));
+        assert!(svg.contains("<tspan x=\"0\" fill=\"#d2d2d2\">&amp;&amp;</tspan><tspan x=\"14.4\""));
+    }
+
+    #[test]
+    fn svg_embeds_a_subset() {
+        let data = crate::testutil::font_data();
+        let font = FontRef::try_from_slice(&data).unwrap();
+        let options = SvgOptions {
+            font: Some(&font),
+            embed_font: true,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_svg(&grid(), &options, &mut out).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        let embedded = svg
+            .split("base64,")
+            .nth(1)
+            .and_then(|rest| rest.split(')').next())
+            .unwrap();
+        assert!(svg.contains("font-family: \"ascii\""));
+        assert!(embedded.len() < base64(&data).len() / 2);
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+    }
+}
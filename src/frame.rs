@@ -0,0 +1,200 @@
+use imageproc::image::RgbImage;
+
+use std::borrow::Cow;
+
+/// A contiguous `RGB24` frame.
+///
+/// Rows start `stride` bytes apart, so the data of ffmpeg frames (whose rows are often padded)
+/// can be wrapped without copying it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub width: usize,
+    pub height: usize,
+    /// Bytes from the start of one row to the start of the next (`>= width * 3`).
+    pub stride: usize,
+    pub data: Cow<'a, [u8]>,
+}
+
+impl Frame<'static> {
+    /// Creates a black frame with the given size.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            stride: width * 3,
+            data: Cow::Owned(vec![0; width * height * 3]),
+        }
+    }
+
+    /// Takes ownership of packed `RGB` data (no padding between rows).
+    ///
+    /// Returns `None` if `data` doesn't contain exactly `width * height` pixels.
+    pub fn from_vec(width: usize, height: usize, data: Vec<u8>) -> Option<Self> {
+        if data.len() != width * height * 3 {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            stride: width * 3,
+            data: Cow::Owned(data),
+        })
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Wraps the given `data` without copying it.
+    ///
+    /// Returns `None` if `stride` is smaller than a row or `data` is too short to hold `height`
+    /// rows. The last row doesn't need to be padded.
+    pub fn from_raw(width: usize, height: usize, stride: usize, data: &'a [u8]) -> Option<Self> {
+        if stride < width * 3 || data.len() < Self::min_len(width, height, stride) {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            stride,
+            data: Cow::Borrowed(data),
+        })
+    }
+
+    /// Wraps packed `RGB` data (no padding between rows) with the given `width`.
+    ///
+    /// Returns `None` if `data` doesn't consist of whole rows.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let pixels = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+    ///
+    /// let frame = ascii::Frame::from_packed(1, &pixels).unwrap();
+    /// assert_eq!(frame.height, 3);
+    /// assert_eq!(frame.pixel(0, 1), (0, 255, 0));
+    /// ```
+    pub fn from_packed(width: usize, data: &'a [u8]) -> Option<Self> {
+        let row = width * 3;
+        if row == 0 || !data.len().is_multiple_of(row) {
+            return None;
+        }
+        Self::from_raw(width, data.len() / row, row, data)
+    }
+
+    fn min_len(width: usize, height: usize, stride: usize) -> usize {
+        match height {
+            0 => 0,
+            h => stride * (h - 1) + width * 3,
+        }
+    }
+
+    /// The `RGB` bytes of row `y` (without padding).
+    pub fn row(&self, y: usize) -> &[u8] {
+        let start = y * self.stride;
+        &self.data[start..start + self.width * 3]
+    }
+
+    /// The `RGB` bytes of row `y` (without padding), copying borrowed data first.
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        let start = y * self.stride;
+        let len = self.width * 3;
+        &mut self.data.to_mut()[start..start + len]
+    }
+
+    /// Iterates over all rows.
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.height).map(|y| self.row(y))
+    }
+
+    /// Iterates over the pixels of row `y`.
+    pub fn row_pixels(&self, y: usize) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+        self.row(y).chunks_exact(3).map(|p| (p[0], p[1], p[2]))
+    }
+
+    /// Get the pixel at `x`, `y`.
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let i = y * self.stride + x * 3;
+        (self.data[i], self.data[i + 1], self.data[i + 2])
+    }
+
+    /// Set the pixel at `x`, `y`.
+    pub fn set_pixel(&mut self, x: usize, y: usize, (r, g, b): (u8, u8, u8)) {
+        let i = y * self.stride + x * 3;
+        self.data.to_mut()[i..i + 3].copy_from_slice(&[r, g, b]);
+    }
+
+    /// Whether there is no padding between rows.
+    pub fn is_packed(&self) -> bool {
+        self.stride == self.width * 3
+    }
+
+    /// Copies the pixels into a packed buffer (no padding between rows).
+    pub fn to_packed(&self) -> Vec<u8> {
+        if self.is_packed() {
+            return self.data[..self.width * self.height * 3].to_vec();
+        }
+        let mut packed = Vec::with_capacity(self.width * self.height * 3);
+        for row in self.rows() {
+            packed.extend_from_slice(row);
+        }
+        packed
+    }
+
+    /// Copies borrowed data, so the frame can outlive its source.
+    pub fn into_owned(self) -> Frame<'static> {
+        Frame {
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            data: Cow::Owned(self.data.into_owned()),
+        }
+    }
+
+    /// Copies the pixels into a new `RgbImage`.
+    pub fn to_rgb_image(&self) -> RgbImage {
+        RgbImage::from_raw(self.width as u32, self.height as u32, self.to_packed())
+            .expect("packed frame has the size of the image")
+    }
+}
+
+impl From<RgbImage> for Frame<'static> {
+    fn from(image: RgbImage) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        Frame::from_vec(width, height, image.into_raw()).expect("image is packed RGB")
+    }
+}
+
+impl From<&Frame<'_>> for RgbImage {
+    fn from(frame: &Frame<'_>) -> Self {
+        frame.to_rgb_image()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_skip_padding() {
+        let data = [1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        let frame = Frame::from_raw(2, 2, 8, &data).unwrap();
+        assert_eq!(frame.row(1), &[7, 8, 9, 10, 11, 12]);
+        assert_eq!(frame.pixel(1, 0), (4, 5, 6));
+        assert_eq!(frame.to_packed(), [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert!(Frame::from_packed(2, &[0; 7]).is_none());
+        assert!(Frame::from_raw(2, 2, 5, &[0; 12]).is_none());
+        assert!(Frame::from_raw(2, 2, 8, &[0; 13]).is_none());
+    }
+
+    #[test]
+    fn rgb_image_roundtrip() {
+        let mut frame = Frame::new(3, 2);
+        frame.set_pixel(2, 1, (255, 128, 0));
+        let image = frame.to_rgb_image();
+        assert_eq!(image.get_pixel(2, 1).0, [255, 128, 0]);
+        assert_eq!(Frame::from(image), frame);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::Frame;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Background color of every cell.
+pub const BACKGROUND: (u8, u8, u8) = (0, 0, 0);
+
+/// A single symbol of the ascii art.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub symbol: char,
+    pub fg: (u8, u8, u8),
+    pub bg: (u8, u8, u8),
+}
+
+impl Cell {
+    /// Get the cell for a pixel with the given `RGB` values.
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            symbol: crate::CHARS[crate::symbol_index(r, g, b)],
+            fg: (r, g, b),
+            bg: BACKGROUND,
+        }
+    }
+}
+
+/// The cells of a `Frame`, one per pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<Cell>,
+}
+
+impl Grid {
+    /// Maps every pixel of the given `frame` to its cell.
+    pub fn from_frame(frame: &Frame) -> Self {
+        let mut indices = Vec::new();
+        crate::symbol_indices(frame, &mut indices);
+        let mut cells = vec![Cell::new(0, 0, 0); frame.width * frame.height];
+        let map_row = |(y, (row, indices)): (usize, (&mut [Cell], &[u8]))| {
+            for ((cell, (r, g, b)), index) in row.iter_mut().zip(frame.row_pixels(y)).zip(indices) {
+                *cell = Cell {
+                    symbol: crate::CHARS[*index as usize],
+                    fg: (r, g, b),
+                    bg: BACKGROUND,
+                };
+            }
+        };
+        let width = frame.width.max(1);
+        #[cfg(feature = "parallel")]
+        cells
+            .par_chunks_mut(width)
+            .zip(indices.par_chunks(width))
+            .enumerate()
+            .for_each(map_row);
+        #[cfg(not(feature = "parallel"))]
+        cells
+            .chunks_mut(width)
+            .zip(indices.chunks(width))
+            .enumerate()
+            .for_each(map_row);
+        Self {
+            width: frame.width,
+            height: frame.height,
+            cells,
+        }
+    }
+
+    /// The cells of row `y`.
+    pub fn row(&self, y: usize) -> &[Cell] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    /// Iterates over all rows.
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        (0..self.height).map(|y| self.row(y))
+    }
+}
@@ -1,17 +1,50 @@
-use crate::{Pixels, wait_for_terminal_scale};
+use crate::atlas::GlyphAtlas;
+use crate::{Frame, Grid, wait_for_terminal_scale};
 
-use ab_glyph::{FontRef, PxScale};
-use imageproc::drawing::draw_text_mut;
+use ab_glyph::{Font, FontRef, PxScale, point};
 use imageproc::image::{ImageBuffer, Rgb, RgbImage};
 use jpeg_decoder::Decoder;
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
 use std::fs::File;
 use std::io::BufReader;
+use std::ops::Range;
 use std::path::Path;
 
-/// Get `pixels`, `width` and `height` of the given `image path` after scaling it down to the given
-/// `max_widh`.
-pub fn get_pixels(path: &str, max_width: Option<f64>) -> (Vec<u8>, u16, u16) {
+/// Metrics of a font (in pixels) at a given font size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+    /// Horizontal advance of a glyph (the same for all glyphs of a monospace font).
+    pub advance: f32,
+    /// Distance from the baseline to the top of the line.
+    pub ascent: f32,
+    /// Distance from the baseline to the bottom of the line (negative).
+    pub descent: f32,
+    pub line_gap: f32,
+}
+
+impl FontMetrics {
+    /// Get the metrics of the given `font` with an em size of `font_size` pixels.
+    pub fn new(font: &impl Font, font_size: f32) -> Self {
+        let px = font_size / font.units_per_em().unwrap_or(1000.0);
+        Self {
+            advance: font.h_advance_unscaled(font.glyph_id('@')) * px,
+            ascent: font.ascent_unscaled() * px,
+            descent: font.descent_unscaled() * px,
+            line_gap: font.line_gap_unscaled() * px,
+        }
+    }
+
+    /// Distance between the baselines of two lines.
+    pub fn line_height(&self) -> f32 {
+        self.ascent - self.descent + self.line_gap
+    }
+}
+
+/// Get the `Frame` of the given `image path` after scaling it down to the given `max_widh`.
+pub fn get_pixels(path: &str, max_width: Option<f64>) -> Frame<'static> {
     let file = File::open(path).expect("Invalid file path");
 
     let mut decoder = Decoder::new(BufReader::new(file));
@@ -30,59 +63,230 @@ pub fn get_pixels(path: &str, max_width: Option<f64>) -> (Vec<u8>, u16, u16) {
     let (w, h) = decoder.scale(new_width, new_height).expect("scale failed");
     let pixels = decoder.decode().expect("failed to decode");
 
-    (pixels, w, h)
+    Frame::from_vec(w as usize, h as usize, pixels).expect("decoded image is not RGB")
 }
 
 /// Draws the given `image path` to stdout after scaling it to `max_width`.
 pub fn draw(path: &str, max_width: Option<f64>) {
-    let (pixels, w, h) = get_pixels(path, max_width);
-    let rows = crate::format_pixels(&pixels, w);
+    let frame = get_pixels(path, max_width);
+
+    wait_for_terminal_scale(frame.width as u32 * 2, frame.height as u32);
+
+    crate::draw(&frame);
+}
+
+/// Options of `get_image_buf_with`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions {
+    /// Font size (em size) in pixels.
+    pub font_size: f32,
+    /// Width of a cell in pixels, defaults to the advance of two glyphs.
+    pub cell_width: Option<u32>,
+    /// Height of a cell in pixels, defaults to the line height of the font.
+    pub cell_height: Option<u32>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            font_size: 7.0,
+            cell_width: None,
+            cell_height: None,
+        }
+    }
+}
+
+/// Size of the cells and the position of the glyphs inside of them.
+///
+/// Like `crate::draw`, every cell consists of two glyphs, so the cells are about square.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub metrics: FontMetrics,
+    /// Scale of the glyphs (`ab_glyph` scales by the height of the font, not by its em size).
+    pub scale: PxScale,
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// Distance from the top of a cell to the baseline of its glyphs.
+    pub baseline: f32,
+    /// Distance from the left of a cell to its first glyph.
+    pub offset: f32,
+}
+
+impl Layout {
+    pub fn new(font: &impl Font, options: &RenderOptions) -> Self {
+        let metrics = FontMetrics::new(font, options.font_size);
+        let height = metrics.ascent - metrics.descent;
+        let cell_width = options
+            .cell_width
+            .unwrap_or((metrics.advance * 2.0).round() as u32)
+            .max(1);
+        let cell_height = options
+            .cell_height
+            .unwrap_or(metrics.line_height().round() as u32)
+            .max(1);
+        Self {
+            metrics,
+            scale: PxScale::from(height),
+            cell_width,
+            cell_height,
+            // center the glyphs in the cell
+            baseline: (cell_height as f32 - height) / 2.0 + metrics.ascent,
+            offset: (cell_width as f32 - metrics.advance * 2.0) / 2.0,
+        }
+    }
+
+    /// Size of the image of a frame with the given size.
+    pub fn image_size(&self, width: usize, height: usize) -> (u32, u32) {
+        (
+            width as u32 * self.cell_width,
+            height as u32 * self.cell_height,
+        )
+    }
+
+    /// Draws the `cell` at `column`, `row` of the `image`, glyphs are clipped to the cell.
+    pub fn draw_cell(
+        &self,
+        image: &mut RgbImage,
+        font: &impl Font,
+        column: u32,
+        row: u32,
+        cell: &crate::Cell,
+    ) {
+        if cell.symbol == ' ' {
+            return;
+        }
+        let left = column * self.cell_width;
+        let top = row * self.cell_height;
+        for i in 0..2 {
+            let x = left as f32 + self.offset + i as f32 * self.metrics.advance;
+            let columns = left..left + self.cell_width;
+            self.draw_glyph(image, font, cell.symbol, (x, top), columns, cell.fg);
+        }
+    }
+
+    /// Draws the glyph of `symbol` starting at `x` into the row of cells starting at `top`, the
+    /// glyph is clipped to the row and the `columns` (pixels) of the `image`.
+    pub fn draw_glyph(
+        &self,
+        image: &mut RgbImage,
+        font: &impl Font,
+        symbol: char,
+        (x, top): (f32, u32),
+        columns: Range<u32>,
+        (r, g, b): (u8, u8, u8),
+    ) {
+        let position = point(x, top as f32 + self.baseline);
+        let glyph = font
+            .glyph_id(symbol)
+            .with_scale_and_position(self.scale, position);
+        let Some(glyph) = font.outline_glyph(glyph) else {
+            return;
+        };
+        let bounds = glyph.px_bounds();
+        glyph.draw(|gx, gy, coverage| {
+            let x = bounds.min.x as i32 + gx as i32;
+            let y = bounds.min.y as i32 + gy as i32;
+            let in_cell = (columns.start as i32..columns.end as i32).contains(&x)
+                && (top as i32..(top + self.cell_height) as i32).contains(&y);
+            if !in_cell || x as u32 >= image.width() || y as u32 >= image.height() {
+                return;
+            }
+            let coverage = coverage.clamp(0.0, 1.0);
+            let pixel = image.get_pixel_mut(x as u32, y as u32);
+            for (c, v) in pixel.0.iter_mut().zip([r, g, b]) {
+                *c = (*c as f32 * (1.0 - coverage) + v as f32 * coverage).round() as u8;
+            }
+        });
+    }
+}
+
+/// Get `ImageBuffer` with the given frame (using the default `RenderOptions`).
+pub fn get_image_buf(font: &FontRef<'_>, frame: &Frame) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    get_image_buf_with(font, frame, &RenderOptions::default())
+}
 
-    wait_for_terminal_scale(w as u32 * 2, h as u32);
+/// Get `ImageBuffer` with the given frame, with the cells laid out according to the metrics of
+/// the `font`.
+pub fn get_image_buf_with(
+    font: &FontRef<'_>,
+    frame: &Frame,
+    options: &RenderOptions,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    Renderer::new(font, options).render(frame)
+}
 
-    crate::draw(rows);
+/// Renders frames with a `GlyphAtlas`, so the glyphs are only rasterized once.
+///
+/// Create one renderer for all frames of a video, instead of calling `get_image_buf` per frame.
+pub struct Renderer<'a> {
+    font: &'a FontRef<'a>,
+    atlas: GlyphAtlas,
 }
 
-/// Get `ImageBuffer` with the given pixels.
-pub fn get_image_buf(font: &FontRef<'_>, pixels: &Pixels) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let kerning: u32 = 4;
-    let font_size = 12.0;
-    let font_scale = PxScale {
-        x: font_size,
-        y: font_size,
-    };
-
-    let width = pixels[0].len() as u32 * (font_size as u32 - kerning);
-    let height = pixels.len() as u32 * (font_size as u32 - kerning);
-
-    let mut image = RgbImage::new(width, height);
-
-    let mut row_index = 0;
-
-    for row in pixels {
-        let mut pixel_index = 0;
-        for (r, g, b) in row {
-            let l = crate::get_lightness(*r, *g, *b);
-            let s = crate::symbol(l).to_string();
-            draw_text_mut(
-                &mut image,
-                Rgb([*r, *g, *b]),
-                pixel_index * (font_scale.x as i32 - kerning as i32),
-                row_index * (font_scale.y as i32 - kerning as i32),
-                font_scale,
-                &font,
-                &s,
-            );
-            pixel_index += 1;
+impl<'a> Renderer<'a> {
+    pub fn new(font: &'a FontRef<'a>, options: &RenderOptions) -> Self {
+        let layout = Layout::new(font, options);
+        Self {
+            font,
+            atlas: GlyphAtlas::new(font, layout, &crate::CHARS),
+        }
+    }
+
+    pub fn layout(&self) -> &Layout {
+        self.atlas.layout()
+    }
+
+    /// Renders the `frame` into a new image.
+    pub fn render(&self, frame: &Frame) -> RgbImage {
+        let (width, height) = self.layout().image_size(frame.width, frame.height);
+        let mut image = RgbImage::new(width, height);
+        self.render_into(frame, &mut image);
+        image
+    }
+
+    /// Renders the `frame` into the given `image`, which has to be (at least) as large as
+    /// `Layout::image_size` of the frame.
+    pub fn render_into(&self, frame: &Frame, image: &mut RgbImage) {
+        let width = image.width() as usize;
+        let band_len = width * 3 * self.layout().cell_height as usize;
+        if band_len == 0 {
+            return;
+        }
+        let grid = Grid::from_frame(frame);
+        let render_band = |(y, band): (usize, &mut [u8])| {
+            band.fill(0);
+            let mut missing = Vec::new();
+            for (x, cell) in grid.row(y).iter().enumerate() {
+                if !self.atlas.blit(band, width, x, cell) {
+                    missing.push((x as u32, y as u32, *cell));
+                }
+            }
+            missing
+        };
+        #[cfg(feature = "parallel")]
+        let missing: Vec<_> = image
+            .par_chunks_mut(band_len)
+            .take(frame.height)
+            .enumerate()
+            .flat_map_iter(render_band)
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let missing: Vec<_> = image
+            .chunks_mut(band_len)
+            .take(frame.height)
+            .enumerate()
+            .flat_map(render_band)
+            .collect();
+        // symbols that aren't in the atlas are drawn the slow way
+        for (x, y, cell) in missing {
+            self.layout().draw_cell(image, self.font, x, y, &cell);
         }
-        row_index += 1;
     }
-    image
 }
 
-/// Draws the given `pixels` with the given `font` to the given `target` path.
-pub fn draw_to_file(target: &str, font: &FontRef<'_>, pixels: &Pixels) {
-    let image = get_image_buf(font, pixels);
+/// Draws the given `frame` with the given `font` to the given `target` path.
+pub fn draw_to_file(target: &str, font: &FontRef<'_>, frame: &Frame) {
+    let image = get_image_buf(font, frame);
     draw_buf_to_file(target, &image);
 }
 
@@ -90,3 +294,126 @@ pub fn draw_buf_to_file(target: &str, buf: &ImageBuffer<Rgb<u8>, Vec<u8>>) {
     let path = Path::new(&target);
     buf.save(path).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Cell;
+    use crate::testutil::font_data;
+
+    #[test]
+    fn metrics_scale_with_the_font_size() {
+        let data = font_data();
+        let font = FontRef::try_from_slice(&data).unwrap();
+        let em = font.units_per_em().unwrap();
+        let unscaled = FontMetrics::new(&font, em);
+        assert_eq!(
+            unscaled.advance,
+            font.h_advance_unscaled(font.glyph_id('@'))
+        );
+        assert_eq!(unscaled.ascent, font.ascent_unscaled());
+
+        let metrics = FontMetrics::new(&font, em / 10.0);
+        assert!((metrics.advance - unscaled.advance / 10.0).abs() < 1e-3);
+        assert!(metrics.descent < 0.0);
+        assert_eq!(
+            metrics.line_height(),
+            metrics.ascent - metrics.descent + metrics.line_gap
+        );
+    }
+
+    #[test]
+    fn cells_are_sized_by_the_metrics() {
+        let data = font_data();
+        let font = FontRef::try_from_slice(&data).unwrap();
+        let options = RenderOptions {
+            font_size: 20.0,
+            ..Default::default()
+        };
+        let layout = Layout::new(&font, &options);
+        let metrics = FontMetrics::new(&font, 20.0);
+        assert_eq!(layout.cell_width, (metrics.advance * 2.0).round() as u32);
+        assert_eq!(layout.cell_height, metrics.line_height().round() as u32);
+        assert_eq!(
+            layout.scale,
+            PxScale::from(metrics.ascent - metrics.descent)
+        );
+        assert!(layout.offset.abs() <= 0.5);
+        assert_eq!(
+            layout.image_size(3, 2),
+            (3 * layout.cell_width, 2 * layout.cell_height)
+        );
+        let frame = Frame::new(3, 2);
+        let image = get_image_buf_with(&font, &frame, &options);
+        assert_eq!(image.dimensions(), layout.image_size(3, 2));
+    }
+
+    #[test]
+    fn custom_cell_size_centers_the_glyphs() {
+        let data = font_data();
+        let font = FontRef::try_from_slice(&data).unwrap();
+        let options = RenderOptions {
+            font_size: 10.0,
+            cell_width: Some(30),
+            cell_height: Some(40),
+        };
+        let layout = Layout::new(&font, &options);
+        let metrics = layout.metrics;
+        assert_eq!((layout.cell_width, layout.cell_height), (30, 40));
+        assert_eq!(layout.offset, (30.0 - metrics.advance * 2.0) / 2.0);
+        let height = metrics.ascent - metrics.descent;
+        assert_eq!(layout.baseline, (40.0 - height) / 2.0 + metrics.ascent);
+        assert_eq!(layout.image_size(2, 5), (60, 200));
+
+        // cells can't be empty
+        let empty = RenderOptions {
+            cell_width: Some(0),
+            cell_height: Some(0),
+            ..options
+        };
+        let layout = Layout::new(&font, &empty);
+        assert_eq!((layout.cell_width, layout.cell_height), (1, 1));
+    }
+
+    #[test]
+    fn glyphs_sit_on_the_baseline() {
+        let data = font_data();
+        let font = FontRef::try_from_slice(&data).unwrap();
+        let options = RenderOptions {
+            font_size: 40.0,
+            ..Default::default()
+        };
+        let layout = Layout::new(&font, &options);
+        let (width, height) = layout.image_size(2, 2);
+        let mut image = RgbImage::new(width, height);
+        let cell = Cell {
+            symbol: 'o',
+            fg: (255, 255, 255),
+            bg: (0, 0, 0),
+        };
+        layout.draw_cell(&mut image, &font, 1, 1, &cell);
+
+        let inked: Vec<_> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel.0[0] > 128)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!inked.is_empty());
+        // only the cell at 1, 1 is drawn
+        assert!(
+            inked
+                .iter()
+                .all(|(x, y)| *x >= layout.cell_width && *y >= layout.cell_height)
+        );
+        // the bottom of the `o` is on the baseline
+        let bottom = inked.iter().map(|(_, y)| *y).max().unwrap() as f32 + 1.0;
+        let baseline = layout.cell_height as f32 + layout.baseline;
+        assert!((bottom - baseline).abs() <= 1.0, "{bottom} vs {baseline}");
+        // and both glyphs are drawn, one advance apart
+        let left = inked.iter().map(|(x, _)| *x).min().unwrap() as f32;
+        let right = inked.iter().map(|(x, _)| *x).max().unwrap() as f32 + 1.0;
+        let glyph = right - left - layout.metrics.advance;
+        assert!(glyph > 0.0 && glyph < layout.metrics.advance, "{glyph}");
+    }
+}
@@ -1,48 +1,115 @@
+pub mod animation;
+pub mod atlas;
+pub mod audio;
+pub mod clock;
+pub mod controls;
 pub mod convert;
+pub mod encode;
+pub mod export;
+pub mod frame;
+pub mod grid;
 pub mod image;
+pub mod streams;
+pub mod subset;
+pub mod subtitles;
+#[cfg(test)]
+mod testutil;
+pub mod trim;
 pub mod video;
+pub mod visualize;
 
-use std::time::Duration;
+pub use frame::Frame;
+pub use grid::{Cell, Grid};
 
-pub type Pixels = Vec<Vec<(u8, u8, u8)>>;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use std::io::Write;
+use std::time::Duration;
 
 pub const CHARS: [char; 14] = [
     ' ', '.', ':', '-', '~', '=', '+', '*', 'o', '%', '&', '8', '#', '@',
 ];
 
-/// Prints the given `Pixels` to stdout.
-pub fn draw(pixels: Pixels) {
-    print!("\x1b[?25l"); // hide cursor
-    print!("\x1b[40;2;0;0;0m");
-    for row in pixels {
-        for (r, g, b) in row {
-            let l = get_lightness(r, g, b);
-            let s = symbol(l);
-            print!("\x1b[38;2;{r};{g};{b}m{s}{s}");
-        }
-        println!();
-    }
-    print!("\x1b[0m");
-    print!("\x1b[?25h"); // show cursor
+/// Prints the given `Frame` to stdout.
+pub fn draw(frame: &Frame) {
+    let grid = Grid::from_frame(frame);
+    let mut stdout = std::io::stdout().lock();
+    write!(stdout, "\x1b[?25l").expect("failed to write to stdout"); // hide cursor
+    export::write_ansi(&grid, &mut stdout).expect("failed to write to stdout");
+    write!(stdout, "\x1b[?25h").expect("failed to write to stdout"); // show cursor
 }
 
 /// Get the symbol matching the lightness.
 pub fn symbol(lightness: u8) -> char {
+    CHARS[lightness_index(lightness)]
+}
+
+/// Get the index (into `CHARS`) of the symbol matching the lightness.
+const fn lightness_index(lightness: u8) -> usize {
     match lightness {
-        100 => CHARS[CHARS.len() - 1],
-        0 => CHARS[0],
+        100 => CHARS.len() - 1,
+        0 => 0,
         l => {
             let p = 100 / (CHARS.len() - 2);
-            for i in (1..CHARS.len() - 1).rev() {
-                if l >= (i * p) as u8 {
-                    return CHARS[i];
+            let mut i = CHARS.len() - 2;
+            while i > 0 {
+                if l as usize >= i * p {
+                    return i;
                 }
+                i -= 1;
             }
-            CHARS[0]
+            0
         }
     }
 }
 
+/// Index into `CHARS` for every luma of a pixel, see `luma`.
+pub static SYMBOL_INDEX: [u8; 256] = symbol_index_lut();
+
+const fn symbol_index_lut() -> [u8; 256] {
+    let mut lut = [0; 256];
+    let mut luma = 0;
+    while luma < lut.len() {
+        // the luma as a percentage, rounded (there are no ties)
+        let lightness = (luma * 100 + 127) / 255;
+        lut[luma] = lightness_index(lightness as u8) as u8;
+        luma += 1;
+    }
+    lut
+}
+
+/// Get the luma of the given `RGB` values (BT.601 weights in fixed point, they add up to 256).
+pub fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((77 * r as u16 + 150 * g as u16 + 29 * b as u16) >> 8) as u8
+}
+
+/// Get the index (into `CHARS`) of the symbol of the given `RGB` values, without any float math.
+pub fn symbol_index(r: u8, g: u8, b: u8) -> usize {
+    SYMBOL_INDEX[luma(r, g, b) as usize] as usize
+}
+
+/// Maps every pixel of the `frame` to the index (into `CHARS`) of its symbol.
+///
+/// The luma of a row is computed in a separate pass, which the compiler can vectorize.
+pub fn symbol_indices(frame: &Frame, indices: &mut Vec<u8>) {
+    indices.clear();
+    indices.resize(frame.width * frame.height, 0);
+    let map_row = |(y, row): (usize, &mut [u8])| {
+        for (index, pixel) in row.iter_mut().zip(frame.row(y).chunks_exact(3)) {
+            *index = luma(pixel[0], pixel[1], pixel[2]);
+        }
+        for index in row {
+            *index = SYMBOL_INDEX[*index as usize];
+        }
+    };
+    let width = frame.width.max(1);
+    #[cfg(feature = "parallel")]
+    indices.par_chunks_mut(width).enumerate().for_each(map_row);
+    #[cfg(not(feature = "parallel"))]
+    indices.chunks_mut(width).enumerate().for_each(map_row);
+}
+
 /// Get the lightness of the given `RGB` values.
 pub fn get_lightness(r: u8, g: u8, b: u8) -> u8 {
     let max = r.max(g.max(b));
@@ -86,31 +153,6 @@ pub fn rgb_to_yuv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     )
 }
 
-/// Formats the given pixels (not structured) into a structured form.
-///
-/// # Example
-///
-/// ```rust
-/// let pixels = [255, 0, 0, 0, 255, 0, 0, 0, 255];
-///
-/// let pixels = format_pixels(pixels, 1);
-/// println!("{pixels:?}"); // [[(255, 0, 0)], [(0, 255, 0)], [(0, 0, 255)]]
-/// ```
-pub fn format_pixels(pixels: &[u8], width: u16) -> Pixels {
-    pixels
-        .chunks(width as usize * 3)
-        .map(|chunk| {
-            chunk
-                .chunks(3)
-                .map(|pixel| {
-                    let [r, g, b] = *pixel else { todo!() };
-                    (r, g, b)
-                })
-                .collect::<Vec<_>>()
-        })
-        .collect()
-}
-
 /// Waits until the terminal size is greater than the given `min_widht` and `min_height`.
 fn wait_for_terminal_scale(min_width: u32, min_height: u32) {
     if let Some((mut w, mut h)) = term_size::dimensions() {
@@ -132,62 +174,54 @@ fn wait_for_terminal_scale(min_width: u32, min_height: u32) {
     }
 }
 
-/// Downscale `Pixels` to the given `new_width`.
+/// Downscale the `Frame` to the given `new_width`.
 ///
-/// If the width of the pixels is smaler than `new_width` it will just return None.
+/// If the width of the frame is smaler than `new_width` it will just return None.
 pub fn downscale_pixels(
-    pixels: &Pixels,
+    frame: &Frame,
     new_width: usize,
     new_height: Option<usize>,
-) -> Option<Pixels> {
-    let og_width = pixels[0].len();
+) -> Option<Frame<'static>> {
+    let og_width = frame.width;
     if og_width <= new_width {
         return None;
     }
 
-    let og_height = pixels.len();
+    let og_height = frame.height;
 
     let factor = new_width as f64 / og_width as f64;
     let new_height = new_height.unwrap_or((og_height as f64 * factor).round() as usize);
 
-    let mut down_scaled = Vec::new();
-
-    for hrows in pixels.chunks(og_height / new_height) {
-        let wchunks = hrows
-            .iter()
-            .fold(Vec::new(), |mut acc: Vec<Vec<&[(u8, u8, u8)]>>, hrow| {
-                for (i, chunk) in hrow.chunks(og_width / new_width).enumerate() {
-                    if acc.get(i).is_some() {
-                        acc[i].push(chunk);
-                    } else {
-                        acc.push(Vec::from([chunk]));
-                    }
-                }
-                acc
-            });
-
-        let mut new_hrow = Vec::with_capacity(new_width);
-        for chunks in wchunks {
-            let mut r = Vec::new();
-            let mut g = Vec::new();
-            let mut b = Vec::new();
-            for rows in chunks {
-                for pixel in rows {
-                    r.push(pixel.0 as usize);
-                    g.push(pixel.1 as usize);
-                    b.push(pixel.2 as usize);
+    // every output pixel is the average of a `block_width` x `block_height` block, the blocks at
+    // the right and bottom edge may be smaller.
+    let block_width = (og_width / new_width.max(1)).max(1);
+    let block_height = (og_height / new_height.max(1)).max(1);
+    let width = og_width.div_ceil(block_width);
+    let height = og_height.div_ceil(block_height);
+
+    let mut down_scaled = Frame::new(width, height);
+
+    let downscale_row = |(y, new_row): (usize, &mut [u8])| {
+        let rows = y * block_height..((y + 1) * block_height).min(og_height);
+        for x in 0..width {
+            let cols = x * block_width..((x + 1) * block_width).min(og_width);
+            let mut sum = [0usize; 3];
+            for row in rows.clone() {
+                for pixel in frame.row(row)[cols.start * 3..cols.end * 3].chunks_exact(3) {
+                    sum[0] += pixel[0] as usize;
+                    sum[1] += pixel[1] as usize;
+                    sum[2] += pixel[2] as usize;
                 }
             }
-            let gavg = |values: &[usize]| {
-                let len = values.len();
-                let sum: usize = values.iter().sum();
-                (sum / len) as u8
-            };
-            let avg = (gavg(&r), gavg(&g), gavg(&b));
-            new_hrow.push(avg);
+            let len = rows.len() * cols.len();
+            new_row[x * 3..x * 3 + 3].copy_from_slice(&sum.map(|v| (v / len) as u8));
         }
-        down_scaled.push(new_hrow);
-    }
+    };
+    let rows = down_scaled.data.to_mut();
+    #[cfg(feature = "parallel")]
+    rows.par_chunks_mut(width * 3).enumerate().for_each(downscale_row);
+    #[cfg(not(feature = "parallel"))]
+    rows.chunks_mut(width * 3).enumerate().for_each(downscale_row);
 
     Some(down_scaled)
 }
@@ -205,6 +239,30 @@ mod tests {
         assert_eq!(rgb_to_yuv(255, 0, 0), (76, 84, 255));
     }
 
+    #[test]
+    fn symbol_lookup_table() {
+        for luma in 0..=255 {
+            let index = SYMBOL_INDEX[luma as usize] as usize;
+            let lightness = (luma as f64 / 255.0 * 100.0).round() as u8;
+            assert_eq!(CHARS[index], symbol(lightness));
+            // grays are their own luma
+            assert_eq!(symbol_index(luma, luma, luma), index);
+        }
+        // green looks brighter than red, red brighter than blue
+        assert!(symbol_index(0, 255, 0) > symbol_index(255, 0, 0));
+        assert!(symbol_index(255, 0, 0) > symbol_index(0, 0, 255));
+    }
+
+    #[test]
+    fn frame_symbol_indices() {
+        // the white pixel is padding
+        let pixels = [0, 0, 0, 255, 0, 0, 255, 255, 255, 0, 255, 0, 0, 0, 0];
+        let frame = Frame::from_raw(2, 2, 9, &pixels).unwrap();
+        let mut indices = vec![1; 8];
+        symbol_indices(&frame, &mut indices);
+        assert_eq!(indices, [0, 3, 7, 0]);
+    }
+
     // #[test]
     // fn convert_speed() {
     //     let times = 100;
@@ -1,4 +1,9 @@
-use ascii::video;
+use ascii::encode::EncoderOptions;
+use ascii::streams::{self, StreamError, StreamSelector};
+use ascii::subtitles::SubtitleSource;
+use ascii::trim::{self, TimeRange};
+use ascii::video::{self, InputOptions, PlaybackOptions};
+use ascii::visualize::{AudioView, Visualization};
 
 use ab_glyph::FontRef;
 
@@ -6,13 +11,212 @@ use ffmpeg_next::software::scaling::flag::Flags;
 
 const MAX_WIDTH: f64 = 15.0 * 32.0;
 
+const USAGE: &str = "\
+usage: ascii <input> [output] [--start <time>] [--end <time> | --duration <time>]
+             [--volume <percent>] [--overlay vu|spectrum]
+             [--visualizer waveform|spectrum|spectrogram]
+             [--video <stream>] [--audio <stream>] [--list-streams]
+             [--subtitles <stream|file>]
+
+Plays <input> in the terminal, or renders it to [output].
+Times are seconds or [h:]m:s, e.g. 90, 1:30 or 1:02:03.5.
+Streams are selected by their index or language (e.g. 2 or eng), --list-streams lists them.
+Subtitles are text subtitle streams or .srt, .vtt or .ass files, they are burned into [output].
+The volume (0 to 200, 100 by default) and the overlay only apply to the playback.
+Inputs without video are shown as the visualizer (spectrum by default).";
+
+/// Command line arguments.
+struct Args {
+    input: String,
+    output: Option<String>,
+    range: TimeRange,
+    visualizer: AudioView,
+    /// `1.0` is the original volume.
+    volume: f32,
+    overlay: Option<Visualization>,
+    video: StreamSelector,
+    audio: StreamSelector,
+    subtitles: SubtitleSource,
+    /// Only list the streams of the input.
+    list_streams: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut paths = Vec::new();
+        let (mut start, mut end, mut duration) = (None, None, None);
+        let (mut volume, mut overlay, mut visualizer) = (1.0, None, AudioView::default());
+        let (mut video, mut audio, mut list_streams) =
+            (StreamSelector::Best, StreamSelector::Best, false);
+        let mut subtitles = SubtitleSource::None;
+        while let Some(arg) = args.next() {
+            let time = match arg.as_str() {
+                "--video" | "--audio" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("{arg} needs an index or language"))?;
+                    let selector = match arg.as_str() {
+                        "--video" => &mut video,
+                        _ => &mut audio,
+                    };
+                    *selector = StreamSelector::parse(&value);
+                    continue;
+                }
+                "--subtitles" => {
+                    let value = args.next().ok_or("--subtitles needs a stream or a file")?;
+                    subtitles = SubtitleSource::parse(&value);
+                    continue;
+                }
+                "--list-streams" => {
+                    list_streams = true;
+                    continue;
+                }
+                "--volume" => {
+                    let value = args.next().ok_or("--volume needs a percentage")?;
+                    volume = match value.parse::<f32>() {
+                        Ok(percent) if (0.0..=200.0).contains(&percent) => percent / 100.0,
+                        _ => return Err(format!("{value} is not a volume from 0 to 200")),
+                    };
+                    continue;
+                }
+                "--overlay" => {
+                    let value = args.next().ok_or("--overlay needs vu or spectrum")?;
+                    overlay = Some(
+                        Visualization::from_name(&value)
+                            .ok_or_else(|| format!("{value} is not vu or spectrum"))?,
+                    );
+                    continue;
+                }
+                "--visualizer" => {
+                    let value = args.next().ok_or("--visualizer needs a view")?;
+                    visualizer = AudioView::from_name(&value).ok_or_else(|| {
+                        format!("{value} is not waveform, spectrum or spectrogram")
+                    })?;
+                    continue;
+                }
+                "--start" => &mut start,
+                "--end" => &mut end,
+                "--duration" => &mut duration,
+                option if option.starts_with("--") => {
+                    return Err(format!("unknown option {option}"));
+                }
+                _ => {
+                    paths.push(arg);
+                    continue;
+                }
+            };
+            let value = args.next().ok_or_else(|| format!("{arg} needs a time"))?;
+            *time = Some(trim::parse_time(&value).ok_or_else(|| format!("{value} is not a time"))?);
+        }
+
+        let start = start.unwrap_or_default();
+        let range = match (end, duration) {
+            (Some(_), Some(_)) => return Err("--end and --duration exclude each other".into()),
+            (end, None) => TimeRange::new(start, end),
+            (None, Some(duration)) => {
+                let end = start.checked_add(duration).ok_or("the end is too late")?;
+                TimeRange::new(start, Some(end))
+            }
+        };
+        if range.end.is_some_and(|end| end <= range.start) {
+            return Err("the end is before the start".into());
+        }
+
+        let mut paths = paths.into_iter();
+        let input = paths.next().ok_or("no input")?;
+        let output = paths.next();
+        if let Some(path) = paths.next() {
+            return Err(format!("unexpected argument {path}"));
+        }
+        Ok(Self {
+            input,
+            output,
+            range,
+            visualizer,
+            volume,
+            overlay,
+            video,
+            audio,
+            subtitles,
+            list_streams,
+        })
+    }
+}
+
 // PKG_CONFIG_PATH=$PKG_CONFIG_PATH:/opt/homebrew/lib/pkgconfig cargo run --release
 
 fn main() {
+    if std::env::args().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return;
+    }
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
     ffmpeg_next::init().unwrap();
 
+    if args.list_streams {
+        match streams::list_streams(&args.input) {
+            Ok(streams) => streams.iter().for_each(|stream| println!("{stream}")),
+            Err(err) => {
+                eprintln!("Couldn't open {}: {err}", args.input);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
     let font = FontRef::try_from_slice(include_bytes!("/Users/ben/Library/Fonts/JetBrainsMonoNerdFont-Regular.ttf")).unwrap();
 
+    let input = InputOptions {
+        range: args.range,
+        visualizer: args.visualizer,
+        video: args.video,
+        audio: args.audio,
+        subtitles: args.subtitles,
+    };
+    let result = match &args.output {
+        Some(output) => video::draw_to_file(
+            &args.input,
+            output,
+            &font,
+            Flags::BICUBIC,
+            Some(MAX_WIDTH),
+            &EncoderOptions::default(),
+            &input,
+        ),
+        None => video::draw_with(
+            &args.input,
+            Flags::BICUBLIN,
+            MAX_WIDTH,
+            &PlaybackOptions {
+                input,
+                volume: args.volume,
+                overlay: args.overlay,
+                ..Default::default()
+            },
+        ),
+    };
+    match result {
+        Ok(()) => {}
+        Err(err @ StreamError::NotFound(..)) => {
+            eprintln!("{}: {err}, see --list-streams", args.input);
+            std::process::exit(2);
+        }
+        Err(err @ StreamError::Encode(_)) => {
+            eprintln!("{}: {err}", args.output.as_deref().unwrap_or_default());
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("Couldn't open {}: {err}", args.input);
+            std::process::exit(1);
+        }
+    }
+
     // video::draw_to_file("examples/BigBuckBunny.mp4", "tmp/out.mp4", &font, Flags::BICUBIC, Some(MAX_WIDTH));
 
     // 15 * 32: 2115s
@@ -43,3 +247,63 @@ fn main() {
     // video::draw("assets/Flashback.mp4", Flags::BICUBLIN, Some(MAX_WIDTH));
     // video::draw("examples/BigBuckBunny.mp4", Flags::BICUBLIN, Some(MAX_WIDTH));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn parse_range() {
+        let args = parse("in.mp4 out.mp4 --start 1:30 --end 1:32.5").unwrap();
+        assert_eq!(
+            (args.input.as_str(), args.output.as_deref()),
+            ("in.mp4", Some("out.mp4"))
+        );
+        assert_eq!(
+            args.range,
+            TimeRange::new(Duration::from_secs(90), Some(Duration::from_millis(92_500)))
+        );
+        let args = parse("in.mp4 --start 10 --duration 5").unwrap();
+        assert_eq!(
+            args.range,
+            TimeRange::with_duration(Duration::from_secs(10), Duration::from_secs(5))
+        );
+        assert_eq!(parse("in.mp4").unwrap().range, TimeRange::default());
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        assert_eq!(
+            parse("in.mp4 --end 5 --duration 2").err().as_deref(),
+            Some("--end and --duration exclude each other")
+        );
+        assert_eq!(
+            parse("in.mp4 --start 5 --end 0:05").err().as_deref(),
+            Some("the end is before the start")
+        );
+        assert_eq!(
+            parse("in.mp4 --start 1:x").err().as_deref(),
+            Some("1:x is not a time")
+        );
+        assert_eq!(
+            parse("in.mp4 --start 1e300").err().as_deref(),
+            Some("1e300 is not a time")
+        );
+        assert_eq!(
+            parse("in.mp4 --start 1e19 --duration 1e19")
+                .err()
+                .as_deref(),
+            Some("the end is too late")
+        );
+        assert_eq!(
+            parse("in.mp4 --end").err().as_deref(),
+            Some("--end needs a time")
+        );
+    }
+}
@@ -0,0 +1,327 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::format::context::Input;
+use ffmpeg::format::stream::{Disposition, Stream};
+use ffmpeg::media::Type as MediaType;
+
+use std::fmt;
+
+/// Which of the streams of a kind (video or audio) of an input is used.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum StreamSelector {
+    /// The stream ffmpeg considers the best.
+    #[default]
+    Best,
+    /// The stream with the index, among all streams of the input (see `list_streams`).
+    Index(usize),
+    /// The first stream in the language (like `eng`, ignoring the case), default streams first.
+    Language(String),
+}
+
+impl StreamSelector {
+    /// An index (like `2`) or a language (like `eng`).
+    pub fn parse(selector: &str) -> Self {
+        match selector.parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Language(selector.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for StreamSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Best => write!(f, "best"),
+            Self::Index(index) => write!(f, "#{index}"),
+            Self::Language(language) => write!(f, "in {language}"),
+        }
+    }
+}
+
+/// A stream of an input and its metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub index: usize,
+    pub medium: MediaType,
+    /// Name of the codec, like `h264` or `aac`.
+    pub codec: String,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    /// Channels of audio streams.
+    pub channels: Option<u16>,
+    /// Sample rate of audio streams.
+    pub sample_rate: Option<u32>,
+    /// Width and height of video streams.
+    pub resolution: Option<(u32, u32)>,
+    /// Whether the stream is played by default.
+    pub default: bool,
+    /// Whether the video stream is a picture (like the cover of an album).
+    pub cover_art: bool,
+}
+
+impl StreamInfo {
+    pub fn new(stream: &Stream) -> Self {
+        let parameters = stream.parameters();
+        let medium = parameters.medium();
+        let metadata = stream.metadata();
+        let decoder = ffmpeg::codec::context::Context::from_parameters(parameters.clone())
+            .ok()
+            .map(|context| context.decoder());
+        let (mut channels, mut sample_rate, mut resolution) = (None, None, None);
+        match medium {
+            MediaType::Audio => {
+                if let Some(Ok(audio)) = decoder.map(|decoder| decoder.audio()) {
+                    channels = Some(audio.channels());
+                    sample_rate = Some(audio.rate());
+                }
+            }
+            MediaType::Video => {
+                if let Some(Ok(video)) = decoder.map(|decoder| decoder.video()) {
+                    resolution = Some((video.width(), video.height()));
+                }
+            }
+            _ => {}
+        }
+        Self {
+            index: stream.index(),
+            medium,
+            codec: parameters.id().name().to_string(),
+            language: metadata.get("language").map(str::to_string),
+            title: metadata.get("title").map(str::to_string),
+            channels,
+            sample_rate,
+            resolution,
+            default: stream.disposition().contains(Disposition::DEFAULT),
+            cover_art: is_cover_art(stream),
+        }
+    }
+}
+
+impl fmt::Display for StreamInfo {
+    /// Like `#1 audio: aac, eng, 2 channels, 48000 Hz, "Commentary" (default)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let medium = medium_name(self.medium);
+        write!(f, "#{} {medium}: {}", self.index, self.codec)?;
+        if let Some(language) = &self.language {
+            write!(f, ", {language}")?;
+        }
+        if let Some((width, height)) = self.resolution {
+            write!(f, ", {width}x{height}")?;
+        }
+        if let Some(channels) = self.channels {
+            write!(f, ", {channels} channels")?;
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            write!(f, ", {sample_rate} Hz")?;
+        }
+        if let Some(title) = &self.title {
+            write!(f, ", {title:?}")?;
+        }
+        if self.cover_art {
+            write!(f, ", cover art")?;
+        }
+        if self.default {
+            write!(f, " (default)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Why an input can't be played (or rendered) with the selected streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamError {
+    /// The input (or one of its streams) couldn't be opened.
+    Input(ffmpeg::Error),
+    /// The input has no stream of the medium the selector selects.
+    NotFound(MediaType, StreamSelector),
+    /// The rendered video couldn't be encoded.
+    Encode(ffmpeg::Error),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Input(err) => write!(f, "{err}"),
+            Self::Encode(err) => write!(f, "couldn't encode the video: {err}"),
+            Self::NotFound(medium, StreamSelector::Best) => {
+                write!(f, "there is no {} stream", medium_name(*medium))
+            }
+            Self::NotFound(medium, selector) => {
+                write!(f, "there is no {} stream {selector}", medium_name(*medium))
+            }
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// The streams of the input at `path`, in the order of their indices.
+pub fn list_streams(path: &str) -> Result<Vec<StreamInfo>, ffmpeg::Error> {
+    let ictx = ffmpeg::format::input(&path)?;
+    Ok(ictx
+        .streams()
+        .map(|stream| StreamInfo::new(&stream))
+        .collect())
+}
+
+/// The stream of the input at `path` the `selector` selects, `None` if there is none.
+pub fn find_stream(
+    path: &str,
+    medium: MediaType,
+    selector: &StreamSelector,
+) -> Result<Option<StreamInfo>, ffmpeg::Error> {
+    let ictx = ffmpeg::format::input(&path)?;
+    Ok(select_stream(&ictx, medium, selector).map(|stream| StreamInfo::new(&stream)))
+}
+
+/// Get the stream of the `medium` the `selector` selects, `None` if there is none.
+///
+/// Cover art is only selected by its index, it isn't a video.
+pub fn select_stream<'a>(
+    ictx: &'a Input,
+    medium: MediaType,
+    selector: &StreamSelector,
+) -> Option<Stream<'a>> {
+    let is_candidate =
+        |stream: &Stream| stream.parameters().medium() == medium && !is_cover_art(stream);
+    match selector {
+        StreamSelector::Best => ictx.streams().best(medium).filter(is_candidate),
+        StreamSelector::Index(index) => ictx
+            .stream(*index)
+            .filter(|stream| stream.parameters().medium() == medium),
+        StreamSelector::Language(language) => {
+            let in_language = |stream: &Stream| {
+                stream
+                    .metadata()
+                    .get("language")
+                    .is_some_and(|l| l.eq_ignore_ascii_case(language))
+            };
+            let mut streams = ictx
+                .streams()
+                .filter(|stream| is_candidate(stream) && in_language(stream));
+            let first = streams.next()?;
+            let is_default = |stream: &Stream| stream.disposition().contains(Disposition::DEFAULT);
+            match is_default(&first) {
+                true => Some(first),
+                false => Some(streams.find(is_default).unwrap_or(first)),
+            }
+        }
+    }
+}
+
+/// Like `video` or `audio`.
+fn medium_name(medium: MediaType) -> &'static str {
+    match medium {
+        MediaType::Video => "video",
+        MediaType::Audio => "audio",
+        MediaType::Subtitle => "subtitle",
+        MediaType::Data => "data",
+        MediaType::Attachment => "attachment",
+        MediaType::Unknown => "unknown",
+    }
+}
+
+fn is_cover_art(stream: &Stream) -> bool {
+    stream.disposition().contains(Disposition::ATTACHED_PIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{TempPath, av_input, mux, tone_wav, video};
+
+    #[test]
+    fn parse_selectors() {
+        assert_eq!(StreamSelector::parse("2"), StreamSelector::Index(2));
+        assert_eq!(
+            StreamSelector::parse("jpn"),
+            StreamSelector::Language("jpn".into())
+        );
+        assert_eq!(StreamSelector::Index(2).to_string(), "#2");
+    }
+
+    #[test]
+    fn stream_description() {
+        let info = StreamInfo {
+            index: 1,
+            medium: MediaType::Audio,
+            codec: "aac".into(),
+            language: Some("eng".into()),
+            title: Some("Commentary".into()),
+            channels: Some(2),
+            sample_rate: Some(48_000),
+            resolution: None,
+            default: true,
+            cover_art: false,
+        };
+        assert_eq!(
+            info.to_string(),
+            "#1 audio: aac, eng, 2 channels, 48000 Hz, \"Commentary\" (default)"
+        );
+    }
+
+    #[test]
+    fn list_and_select() {
+        let input = av_input(1);
+        let output = input.as_str();
+
+        let streams = list_streams(output).unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].medium, MediaType::Video);
+        assert_eq!(streams[0].resolution, Some((64, 48)));
+        assert_eq!(streams[1].medium, MediaType::Audio);
+        assert_eq!(streams[1].channels, Some(2));
+        assert_eq!(streams[1].sample_rate, Some(48_000));
+
+        let audio = find_stream(output, MediaType::Audio, &StreamSelector::Index(1)).unwrap();
+        assert_eq!(audio, Some(streams[1].clone()));
+        // not an audio stream
+        let video_index = StreamSelector::Index(0);
+        assert_eq!(
+            find_stream(output, MediaType::Audio, &video_index).unwrap(),
+            None
+        );
+        let klingon = StreamSelector::Language("tlh".into());
+        assert_eq!(
+            find_stream(output, MediaType::Audio, &klingon).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn select_by_language() {
+        let video = video(5);
+        let tone = TempPath::new("tone.wav");
+        tone_wav(tone.path(), 1);
+        let input = mux(&[
+            (&video, None, true),
+            (&tone, Some("eng"), true),
+            (&tone, Some("ger"), false),
+            (&tone, Some("ger"), true),
+            (&tone, Some("fre"), false),
+            (&tone, Some("fre"), false),
+        ]);
+        let select = |selector: StreamSelector| {
+            find_stream(input.as_str(), MediaType::Audio, &selector)
+                .unwrap()
+                .map(|stream| stream.index)
+        };
+        let language = |language: &str| StreamSelector::Language(language.into());
+
+        let streams = list_streams(input.as_str()).unwrap();
+        assert_eq!(streams[2].language.as_deref(), Some("ger"));
+        assert_eq!(
+            streams.iter().map(|s| s.default).collect::<Vec<_>>(),
+            [true, true, false, true, false, false]
+        );
+        // the default stream in the language before the first one
+        assert_eq!(select(language("ger")), Some(3));
+        assert_eq!(select(language("GER")), Some(3));
+        // the first one if none is a default stream
+        assert_eq!(select(language("fre")), Some(4));
+        assert_eq!(select(language("eng")), Some(1));
+        assert_eq!(select(StreamSelector::Index(2)), Some(2));
+        assert_eq!(select(language("jpn")), None);
+    }
+}
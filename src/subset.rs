@@ -0,0 +1,231 @@
+//! Subsetting of TrueType fonts, so only the glyphs of the used characters are embedded.
+
+use ab_glyph::{Font, FontRef};
+
+use std::collections::BTreeSet;
+
+/// Sum of all checksums of a font file, see `head.checkSumAdjustment`.
+const CHECKSUM_MAGIC: u32 = 0xB1B0AFBA;
+
+// flags of the components of composite glyphs
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+/// Get a copy of the `font` in which only the glyphs of the `chars` (and the glyphs they are
+/// composed of) have outlines.
+///
+/// The glyph ids stay the same, the other glyphs are just empty, so all tables but `glyf`, `loca`,
+/// `head` and `post` (the glyph names) are copied as they are. `None` if the font has no `glyf`
+/// table (like CFF fonts) or is a collection.
+pub fn subset(font: &FontRef, chars: impl IntoIterator<Item = char>) -> Option<Vec<u8>> {
+    let data = font.font_data();
+    let tables = read_tables(data)?;
+    let table = |tag: &[u8; 4]| tables.iter().find(|(t, _)| t == tag).map(|(_, data)| *data);
+    let (head, glyf, loca) = (table(b"head")?, table(b"glyf")?, table(b"loca")?);
+    let glyph_count = read_u16(table(b"maxp")?, 4)? as usize;
+    let long_offsets = read_u16(head, 50)? == 1;
+    let offsets = (0..=glyph_count)
+        .map(|i| match long_offsets {
+            true => read_u32(loca, i * 4).map(|o| o as usize),
+            false => read_u16(loca, i * 2).map(|o| o as usize * 2),
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let glyph = |id: usize| glyf.get(offsets[id]..offsets[id + 1]);
+
+    // the missing glyph is always kept
+    let mut keep = BTreeSet::new();
+    let mut pending = vec![0];
+    pending.extend(chars.into_iter().map(|c| font.glyph_id(c).0 as usize));
+    while let Some(id) = pending.pop() {
+        if id >= glyph_count || !keep.insert(id) {
+            continue;
+        }
+        pending.extend(components(glyph(id)?)?);
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((glyph_count + 1) * 4);
+    for id in 0..glyph_count {
+        new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&id) {
+            new_glyf.extend(glyph(id)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+    }
+    new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+    let mut new_head = head.to_vec();
+    // long loca offsets, the checksum adjustment is set once the file is written
+    new_head
+        .get_mut(50..52)?
+        .copy_from_slice(&1u16.to_be_bytes());
+    new_head[8..12].fill(0);
+    // version 3 of `post` has no glyph names
+    let mut new_post = table(b"post")?.get(0..32)?.to_vec();
+    new_post[0..4].copy_from_slice(&0x0003_0000u32.to_be_bytes());
+
+    let tables: Vec<_> = tables
+        .iter()
+        // the signature doesn't match anymore
+        .filter(|(tag, _)| tag != b"DSIG")
+        .map(|(tag, table)| match tag {
+            b"glyf" => (*tag, new_glyf.as_slice()),
+            b"loca" => (*tag, new_loca.as_slice()),
+            b"head" => (*tag, new_head.as_slice()),
+            b"post" => (*tag, new_post.as_slice()),
+            _ => (*tag, *table),
+        })
+        .collect();
+    Some(write_font(&data[0..4], &tables))
+}
+
+/// The tables of the font file in the order of their tags.
+fn read_tables(data: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    if data.starts_with(b"ttcf") {
+        return None;
+    }
+    let count = read_u16(data, 4)? as usize;
+    let mut tables = (0..count)
+        .map(|i| {
+            let record = data.get(12 + i * 16..28 + i * 16)?;
+            let offset = read_u32(record, 8)? as usize;
+            let len = read_u32(record, 12)? as usize;
+            Some((
+                record[0..4].try_into().ok()?,
+                data.get(offset..offset + len)?,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    tables.sort_by_key(|(tag, _)| *tag);
+    Some(tables)
+}
+
+/// The ids of the glyphs a composite glyph is made of (none for simple glyphs).
+fn components(glyph: &[u8]) -> Option<Vec<usize>> {
+    let mut ids = Vec::new();
+    // empty glyphs have no header, simple glyphs have a positive number of contours
+    if glyph.is_empty() || read_u16(glyph, 0)? as i16 >= 0 {
+        return Some(ids);
+    }
+    let mut offset = 10;
+    loop {
+        let flags = read_u16(glyph, offset)?;
+        ids.push(read_u16(glyph, offset + 2)? as usize);
+        offset += 4;
+        offset += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            offset += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            offset += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            offset += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            return Some(ids);
+        }
+    }
+}
+
+/// Writes a font file with the `tables` (sorted by their tags), `version` is the `sfntVersion`.
+fn write_font(version: &[u8], tables: &[([u8; 4], &[u8])]) -> Vec<u8> {
+    let count = tables.len() as u16;
+    let entry_selector = count.max(1).ilog2() as u16;
+    let search_range = (1 << entry_selector) * 16;
+    let mut font = version.to_vec();
+    for value in [
+        count,
+        search_range,
+        entry_selector,
+        count * 16 - search_range,
+    ] {
+        font.extend(value.to_be_bytes());
+    }
+    let mut offset = font.len() + tables.len() * 16;
+    for (tag, table) in tables {
+        font.extend(tag);
+        font.extend(checksum(table).to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((table.len() as u32).to_be_bytes());
+        offset += table.len().next_multiple_of(4);
+    }
+    let mut head = None;
+    for (tag, table) in tables {
+        if tag == b"head" {
+            head = Some(font.len());
+        }
+        font.extend(*table);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    if let Some(head) = head {
+        let adjustment = CHECKSUM_MAGIC.wrapping_sub(checksum(&font));
+        font[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+/// Sum of the big endian `u32`s of the data (padded with zeros).
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::font_data;
+
+    #[test]
+    fn keeps_only_the_used_glyphs() {
+        let data = font_data();
+        let font = FontRef::try_from_slice(&data).unwrap();
+        let subset = subset(&font, "a@".chars()).unwrap();
+        assert!(subset.len() < data.len() / 4, "{} bytes", subset.len());
+        assert_eq!(checksum(&subset), CHECKSUM_MAGIC);
+
+        let subset = FontRef::try_from_slice(&subset).unwrap();
+        assert_eq!(subset.glyph_count(), font.glyph_count());
+        for c in ['a', '@'] {
+            let id = subset.glyph_id(c);
+            assert_eq!(id, font.glyph_id(c));
+            assert!(subset.outline(id).is_some(), "{c}");
+            assert_eq!(subset.h_advance_unscaled(id), font.h_advance_unscaled(id));
+        }
+        assert!(subset.outline(subset.glyph_id('b')).is_none());
+    }
+
+    #[test]
+    fn composite_glyphs_keep_their_components() {
+        let data = font_data();
+        let font = FontRef::try_from_slice(&data).unwrap();
+        let subset = FontRef::try_from_slice(&super::subset(&font, ['é']).unwrap())
+            .unwrap()
+            .outline(font.glyph_id('é'))
+            .map(|outline| outline.curves.len());
+        assert_eq!(
+            subset,
+            font.outline(font.glyph_id('é')).map(|o| o.curves.len())
+        );
+    }
+}
@@ -0,0 +1,536 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use crate::image::Layout;
+use crate::streams::{self, StreamError, StreamSelector};
+use crate::trim;
+
+use ab_glyph::Font;
+use ffmpeg::codec::subtitle::{Rect, Subtitle};
+use ffmpeg::format::context::Input;
+use ffmpeg::media::Type as MediaType;
+use ffmpeg::{Rational, Rescale};
+use imageproc::image::RgbImage;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Most lines of the subtitles that are shown at once, further lines are cut off.
+pub const MAX_LINES: usize = 2;
+
+/// Where the subtitles of an input come from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SubtitleSource {
+    #[default]
+    None,
+    /// A text subtitle stream of the input (bitmap subtitles aren't supported).
+    Stream(StreamSelector),
+    /// A `.srt`, `.vtt` or `.ass` file.
+    File(PathBuf),
+}
+
+impl SubtitleSource {
+    /// A file if `source` has the extension of a subtitle file, a stream (see
+    /// `StreamSelector::parse`) otherwise.
+    pub fn parse(source: &str) -> Self {
+        let extension = Path::new(source)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("srt" | "vtt" | "ass" | "ssa") => Self::File(source.into()),
+            _ => Self::Stream(StreamSelector::parse(source)),
+        }
+    }
+}
+
+/// Text that is shown from `start` until `end` (relative to the start of the input).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    /// Plain text (without any formatting), lines are separated by `\n`.
+    pub text: String,
+}
+
+/// The cues of a subtitle track, sorted by their start.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Subtitles {
+    cues: Vec<Cue>,
+}
+
+impl Subtitles {
+    /// Empty cues (and cues that end before they start) are dropped.
+    pub fn new(mut cues: Vec<Cue>) -> Self {
+        cues.retain(|cue| !cue.text.is_empty() && cue.end > cue.start);
+        cues.sort_by_key(|cue| cue.start);
+        Self { cues }
+    }
+
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    /// The lines of all cues shown at `time`.
+    pub fn lines_at(&self, time: Duration) -> Vec<&str> {
+        let started = self.cues.partition_point(|cue| cue.start <= time);
+        self.cues[..started]
+            .iter()
+            .filter(|cue| cue.end > time)
+            .flat_map(|cue| cue.text.lines())
+            .collect()
+    }
+
+    /// Adds the `cue` (of a stream that is decoded while it's played), unless it's empty or there
+    /// already (because the playback went back).
+    pub fn insert(&mut self, cue: Cue) {
+        if cue.text.is_empty() || cue.end <= cue.start || self.cues.contains(&cue) {
+            return;
+        }
+        let index = self.cues.partition_point(|other| other.start <= cue.start);
+        self.cues.insert(index, cue);
+    }
+
+    /// Parses a `.srt`, `.vtt` or `.ass` (or `.ssa`) file.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        // the byte order mark some editors write
+        let text = text.trim_start_matches('\u{feff}');
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("ass") || ext.eq_ignore_ascii_case("ssa") => {
+                Ok(Self::new(parse_ass(text)))
+            }
+            // WebVTT cues are like SubRip cues, with an optional hour
+            Some(ext) if ext.eq_ignore_ascii_case("srt") || ext.eq_ignore_ascii_case("vtt") => {
+                Ok(Self::new(parse_srt(text)))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a srt, vtt or ass file", path.display()),
+            )),
+        }
+    }
+}
+
+/// Decodes the cues of a text subtitle stream from its packets, so they are read with the audio
+/// and video instead of up front.
+pub struct SubtitleDecoder {
+    decoder: ffmpeg::codec::decoder::Subtitle,
+    index: usize,
+    time_base: Rational,
+    /// Cues are relative to the start of the input, like the frames.
+    start_time: i64,
+}
+
+impl SubtitleDecoder {
+    /// The decoder of the text subtitle stream of `ictx` the `selector` selects.
+    pub fn new(ictx: &Input, selector: &StreamSelector) -> Result<Self, StreamError> {
+        let stream = streams::select_stream(ictx, MediaType::Subtitle, selector)
+            .ok_or_else(|| StreamError::NotFound(MediaType::Subtitle, selector.clone()))?;
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+            .and_then(|context| context.decoder().subtitle())
+            .map_err(StreamError::Input)?;
+        Ok(Self {
+            decoder,
+            index: stream.index(),
+            time_base: stream.time_base(),
+            start_time: crate::video::start_time(ictx),
+        })
+    }
+
+    /// Index of the stream the packets are decoded of.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The cue of a `packet` of the stream, `None` if it can't be decoded (or has no timestamp).
+    pub fn decode(&mut self, packet: &ffmpeg::Packet) -> Option<Cue> {
+        let mut subtitle = Subtitle::new();
+        if !self.decoder.decode(packet, &mut subtitle).unwrap_or(false) {
+            return None;
+        }
+        let micros = to_micros(packet.pts()?, self.time_base) - self.start_time;
+        let end = match subtitle.end() {
+            // shown until the end of the packet
+            0 | u32::MAX => micros + to_micros(packet.duration(), self.time_base),
+            end => micros + end as i64 * 1000,
+        };
+        let text: Vec<_> = subtitle
+            .rects()
+            .filter_map(|rect| match rect {
+                Rect::Text(text) => Some(clean_text(text.get())),
+                Rect::Ass(ass) => Some(ass_text(ass.get())),
+                _ => None,
+            })
+            .collect();
+        Some(Cue {
+            start: from_micros(micros + subtitle.start() as i64 * 1000),
+            end: from_micros(end),
+            text: text.join("\n"),
+        })
+    }
+}
+
+fn to_micros(duration: i64, time_base: Rational) -> i64 {
+    duration.rescale(time_base, ffmpeg::rescale::TIME_BASE)
+}
+
+fn from_micros(micros: i64) -> Duration {
+    Duration::from_micros(micros.max(0) as u64)
+}
+
+/// Parses the cues of a SubRip (or WebVTT) file, blocks without a timing line are skipped.
+pub fn parse_srt(text: &str) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let Some((start, end)) = line.split_once("-->") else {
+            continue;
+        };
+        // WebVTT cue settings follow the end
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let text: Vec<_> = lines
+            .by_ref()
+            .take_while(|line| !line.trim().is_empty())
+            .collect();
+        if let (Some(start), Some(end)) = (parse_timestamp(start), parse_timestamp(end)) {
+            cues.push(Cue {
+                start,
+                end,
+                text: clean_text(&text.join("\n")),
+            });
+        }
+    }
+    cues
+}
+
+/// Parses the `Dialogue` lines of the `[Events]` of an Advanced SubStation Alpha file.
+pub fn parse_ass(text: &str) -> Vec<Cue> {
+    // the default order of the fields, the `Format` line can change it
+    let mut format = vec![
+        "layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text",
+    ];
+    let mut cues = Vec::new();
+    let mut events = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            events = line.eq_ignore_ascii_case("[events]");
+        } else if !events {
+            continue;
+        } else if let Some(fields) = line.strip_prefix("Format:") {
+            format = fields.split(',').map(str::trim).collect();
+        } else if let Some(dialogue) = line.strip_prefix("Dialogue:") {
+            // the text is the last field and can contain commas
+            let values: Vec<_> = dialogue.splitn(format.len(), ',').map(str::trim).collect();
+            let value = |name: &str| {
+                let index = format
+                    .iter()
+                    .position(|field| field.eq_ignore_ascii_case(name))?;
+                values.get(index).copied()
+            };
+            let (Some(start), Some(end), Some(text)) =
+                (value("start"), value("end"), value("text"))
+            else {
+                continue;
+            };
+            if let (Some(start), Some(end)) = (trim::parse_time(start), trim::parse_time(end)) {
+                cues.push(Cue {
+                    start,
+                    end,
+                    text: clean_text(text),
+                });
+            }
+        }
+    }
+    cues
+}
+
+/// The text of an ASS event like ffmpeg decodes it (`ReadOrder,Layer,Style,Name,MarginL,MarginR,
+/// MarginV,Effect,Text`, older versions prefix it with `Dialogue:` and the times).
+pub fn ass_text(event: &str) -> String {
+    let text = match event.strip_prefix("Dialogue:") {
+        Some(dialogue) => dialogue.splitn(10, ',').nth(9),
+        None => event.splitn(9, ',').nth(8),
+    };
+    clean_text(text.unwrap_or_default())
+}
+
+/// Parses timestamps like `01:02:03,500` (SubRip) or `02:03.500` (WebVTT).
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    trim::parse_time(&timestamp.trim().replace(',', "."))
+}
+
+/// Removes the formatting (HTML-like tags and ASS override blocks) of the `text`, empty lines
+/// are dropped. A `<` or `{` that doesn't start a tag or block (like in `5 < 6`) is kept.
+fn clean_text(text: &str) -> String {
+    let text = text
+        .replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", " ");
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while let Some(c) = rest.chars().next() {
+        let markup = match c {
+            '<' => tag_len(rest),
+            '{' => closed_len(rest, '}'),
+            _ => None,
+        };
+        let len = markup.unwrap_or_else(|| {
+            plain.push(c);
+            c.len_utf8()
+        });
+        rest = &rest[len..];
+    }
+    let lines: Vec<_> = plain
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join("\n")
+}
+
+/// Tags of SubRip and WebVTT cues, like `<i>`, `<font color="red">`, `<v Ben>` or `<c.yellow>`.
+const TAGS: [&str; 10] = ["b", "i", "u", "s", "font", "v", "c", "lang", "ruby", "rt"];
+
+/// Length of the (opening or closing) tag the `text` starts with, `None` if it doesn't start
+/// with one.
+fn tag_len(text: &str) -> Option<usize> {
+    let len = closed_len(text, '>')?;
+    let tag = &text[1..len - 1];
+    let name = tag.strip_prefix('/').unwrap_or(tag);
+    let name = name.split([' ', '.']).next().unwrap_or_default();
+    TAGS.iter()
+        .any(|tag| tag.eq_ignore_ascii_case(name))
+        .then_some(len)
+}
+
+/// Length of the `text` up to and including the `end` that closes its first character, `None`
+/// if it isn't closed on the same line (or another one is opened first).
+fn closed_len(text: &str, end: char) -> Option<usize> {
+    let open = text.chars().next()?;
+    let len = text[open.len_utf8()..].find([end, open, '\n'])? + open.len_utf8();
+    text[len..].starts_with(end).then_some(len + end.len_utf8())
+}
+
+/// The `line` cut off to `width` characters and the column it starts at to be centered.
+pub fn center(line: &str, width: usize) -> (usize, String) {
+    let line: String = line.chars().take(width).collect();
+    let len = line.chars().count();
+    ((width - len) / 2, line)
+}
+
+/// Burns the `lines` into the bottom of the `image` of a frame rendered with the `layout`, they
+/// are white on a black box and centered.
+pub fn burn_in(image: &mut RgbImage, font: &impl Font, layout: &Layout, lines: &[&str]) {
+    let advance = layout.metrics.advance;
+    if advance <= 0.0 {
+        return;
+    }
+    let columns = (image.width() as f32 / advance) as usize;
+    let lines = &lines[..lines.len().min(MAX_LINES)];
+    // the last line is one row above the bottom
+    let bottom = image.height().saturating_sub(layout.cell_height);
+    for (i, line) in lines.iter().enumerate() {
+        let Some(top) = bottom.checked_sub((lines.len() - i) as u32 * layout.cell_height) else {
+            continue;
+        };
+        let (offset, line) = center(line, columns.saturating_sub(2));
+        // half a glyph of padding on each side
+        let left = ((offset as f32 + 0.5) * advance) as u32;
+        let right = (((offset + line.chars().count()) as f32 + 1.5) * advance) as u32;
+        for y in top..(top + layout.cell_height).min(image.height()) {
+            for x in left..right.min(image.width()) {
+                image.get_pixel_mut(x, y).0 = [0, 0, 0];
+            }
+        }
+        for (column, symbol) in line.chars().enumerate() {
+            let x = (offset + 1 + column) as f32 * advance;
+            let columns = 0..image.width();
+            layout.draw_glyph(image, font, symbol, (x, top), columns, (255, 255, 255));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRT: &str = "\
+1
+00:00:01,000 --> 00:00:02,500
+<i>Hello</i>
+world
+
+2
+00:00:02,000 --> 00:00:04,000
+{\\an8}Again
+";
+
+    #[test]
+    fn parse_srt_cues() {
+        let subtitles = Subtitles::new(parse_srt(SRT));
+        assert_eq!(
+            subtitles.cues()[0],
+            Cue {
+                start: Duration::from_secs(1),
+                end: Duration::from_millis(2500),
+                text: "Hello\nworld".into(),
+            }
+        );
+        assert_eq!(
+            subtitles.lines_at(Duration::from_millis(500)),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            subtitles.lines_at(Duration::from_secs(1)),
+            ["Hello", "world"]
+        );
+        assert_eq!(
+            subtitles.lines_at(Duration::from_millis(2200)),
+            ["Hello", "world", "Again"]
+        );
+        assert_eq!(subtitles.lines_at(Duration::from_millis(2500)), ["Again"]);
+        assert_eq!(
+            subtitles.lines_at(Duration::from_secs(4)),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn parse_vtt_cues() {
+        let vtt = "WEBVTT\n\nNOTE a comment\n\n01:02.000 --> 01:03.000 align:start\n<v Ben>Hi\n";
+        assert_eq!(
+            parse_srt(vtt),
+            [Cue {
+                start: Duration::from_secs(62),
+                end: Duration::from_secs(63),
+                text: "Hi".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_ass_events() {
+        let ass = "\
+[Script Info]
+Title: Test
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Not shown
+Dialogue: 0,0:00:01.50,0:00:03.00,Default,,0,0,0,,{\\i1}One,\\Ntwo
+";
+        assert_eq!(
+            parse_ass(ass),
+            [Cue {
+                start: Duration::from_millis(1500),
+                end: Duration::from_secs(3),
+                text: "One,\ntwo".into(),
+            }]
+        );
+        assert_eq!(ass_text("3,0,Default,,0,0,0,,Hi, there"), "Hi, there");
+    }
+
+    #[test]
+    fn clean_only_tags() {
+        assert_eq!(clean_text("5 < 6 apples"), "5 < 6 apples");
+        assert_eq!(clean_text("a <> b {c"), "a <> b {c");
+        assert_eq!(
+            clean_text("<font color=\"red\">Red</font> <c.yellow.bg_blue>and</c> <B>bold</B>"),
+            "Red and bold"
+        );
+        assert_eq!(clean_text("<v Ben>Hi <3</v>"), "Hi <3");
+        assert_eq!(clean_text("x<y>z"), "x<y>z");
+        assert_eq!(clean_text("{\\an8}Top\\N{\\i1}line"), "Top\nline");
+    }
+
+    #[test]
+    fn insert_decoded_cues() {
+        let cue = |start, text: &str| Cue {
+            start: Duration::from_secs(start),
+            end: Duration::from_secs(start + 1),
+            text: text.into(),
+        };
+        let mut subtitles = Subtitles::default();
+        for cue in [
+            cue(2, "b"),
+            cue(1, "a"),
+            cue(2, "b"),
+            cue(3, ""),
+            cue(2, "c"),
+        ] {
+            subtitles.insert(cue);
+        }
+        assert_eq!(subtitles.cues(), [cue(1, "a"), cue(2, "b"), cue(2, "c")]);
+    }
+
+    #[test]
+    fn decode_a_text_stream() {
+        let path = crate::testutil::subtitled_video();
+        let mut ictx = ffmpeg::format::input(path.path()).unwrap();
+        let mut decoder = SubtitleDecoder::new(&ictx, &StreamSelector::Best).unwrap();
+        assert_eq!(decoder.index(), 1);
+        let cues: Vec<_> = ictx
+            .packets()
+            .filter(|(stream, _)| stream.index() == decoder.index())
+            .filter_map(|(_, packet)| decoder.decode(&packet))
+            .collect();
+        let cue = |start, end, text: &str| Cue {
+            start: Duration::from_millis(start),
+            end: Duration::from_millis(end),
+            text: text.into(),
+        };
+        assert_eq!(cues, [cue(100, 500, "Hello"), cue(610, 900, "Two\nlines")]);
+
+        let klingon = StreamSelector::Language("tlh".into());
+        assert_eq!(
+            SubtitleDecoder::new(&ictx, &klingon).err(),
+            Some(StreamError::NotFound(MediaType::Subtitle, klingon))
+        );
+    }
+
+    #[test]
+    fn burn_in_the_bottom_rows() {
+        let font_data = crate::testutil::font_data();
+        let font = ab_glyph::FontRef::try_from_slice(&font_data).unwrap();
+        let layout = Layout::new(&font, &crate::image::RenderOptions::default());
+        let (width, height) = (20 * layout.cell_width, 6 * layout.cell_height);
+        let mut image = RgbImage::from_pixel(width, height, imageproc::image::Rgb([0, 0, 255]));
+        burn_in(&mut image, &font, &layout, &["Hi", "there", "cut off"]);
+
+        let rows = |cells: std::ops::Range<u32>| {
+            let rows = cells.start * layout.cell_height..cells.end * layout.cell_height;
+            rows.flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| image.get_pixel(x, y).0)
+                .collect::<Vec<_>>()
+        };
+        // the two lines are in the rows above the last one
+        assert!(rows(0..3).iter().all(|pixel| *pixel == [0, 0, 255]));
+        assert!(rows(5..6).iter().all(|pixel| *pixel == [0, 0, 255]));
+        for line in [3..4, 4..5] {
+            let pixels = rows(line);
+            // white text on black, in the middle
+            assert!(pixels.contains(&[0, 0, 0]));
+            assert!(pixels.iter().any(|&[r, g, b]| r > 128 && r == g && g == b));
+            assert_eq!(pixels[0], [0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn parse_sources() {
+        assert_eq!(
+            SubtitleSource::parse("movie.en.SRT"),
+            SubtitleSource::File("movie.en.SRT".into())
+        );
+        assert_eq!(
+            SubtitleSource::parse("eng"),
+            SubtitleSource::Stream(StreamSelector::Language("eng".into()))
+        );
+    }
+
+    #[test]
+    fn center_lines() {
+        assert_eq!(center("abc", 8), (2, "abc".into()));
+        assert_eq!(center("abcdef", 4), (0, "abcd".into()));
+    }
+}
@@ -0,0 +1,161 @@
+//! Helpers shared by the tests.
+
+extern crate ffmpeg_next as ffmpeg;
+
+use crate::audio::WavWriter;
+use crate::encode::{EncoderOptions, VideoWriter, output_extension};
+
+use ffmpeg::format::stream::Disposition;
+use ffmpeg::{Dictionary, Rational};
+use imageproc::image::{Rgb, RgbImage};
+
+use std::path::{Path, PathBuf};
+
+/// The font the tests render with, `ASCII_FONT` or DejaVu Sans Mono.
+pub fn font_data() -> Vec<u8> {
+    let path = std::env::var("ASCII_FONT")
+        .unwrap_or("/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf".to_string());
+    std::fs::read(&path).unwrap_or_else(|err| panic!("failed to read font {path}: {err}"))
+}
+
+/// A path in the temp dir that no other test (or test run) uses, the file is removed when it is
+/// dropped.
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    /// The file name ends with `name` (like `out.mkv`).
+    pub fn new(name: &str) -> Self {
+        let unique = format!(
+            "ascii-{}-{:08x}-{name}",
+            std::process::id(),
+            rand::random::<u32>()
+        );
+        Self(std::env::temp_dir().join(unique))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().expect("the temp dir is UTF-8")
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // the file may not have been written
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Writes a `secs` long 440 Hz tone (48 kHz, stereo) to `path`.
+pub fn tone_wav(path: &Path, secs: u32) {
+    let mut tone = WavWriter::create(path, 48_000, 2).unwrap();
+    let samples: Vec<f32> = (0..48_000 * secs)
+        .flat_map(|i| {
+            let sample = (i as f32 * 440.0 / 48_000.0 * std::f32::consts::TAU).sin() * 0.5;
+            [sample, sample]
+        })
+        .collect();
+    tone.write(&samples).unwrap();
+    tone.finish().unwrap();
+}
+
+/// Writes a 64x48 video at 25 fps with `frames` frames (the container depends on the encoder).
+///
+/// There are no B-frames, so each frame is decoded as soon as its packet is read.
+pub fn video(frames: i64) -> TempPath {
+    ffmpeg::init().unwrap();
+    let options = EncoderOptions {
+        preset: Some("ultrafast".to_string()),
+        ..Default::default()
+    };
+    let path = TempPath::new(&format!("video.{}", output_extension("out.mkv", &options)));
+    let mut writer = VideoWriter::new(
+        path.as_str(),
+        64,
+        48,
+        Rational(25, 1),
+        Rational(1, 25),
+        &options,
+    )
+    .unwrap();
+    for pts in 0..frames {
+        let image = RgbImage::from_pixel(64, 48, Rgb([(pts * 10) as u8, 128, 0]));
+        writer.write(image.as_raw(), 64, 48, pts).unwrap();
+    }
+    writer.finish().unwrap();
+    path
+}
+
+/// Writes a `secs` long 64x48 video at 25 fps with a 48 kHz stereo tone.
+pub fn av_input(secs: u32) -> TempPath {
+    let video = video(25 * secs as i64);
+    let wav = TempPath::new("tone.wav");
+    tone_wav(wav.path(), secs);
+    let output = TempPath::new("av.mkv");
+    crate::convert::convert(wav.as_str(), video.as_str(), output.as_str()).unwrap();
+    output
+}
+
+/// Muxes the first stream of each of the `inputs` into an mkv, with its language and whether it's
+/// played by default.
+pub fn mux(inputs: &[(&TempPath, Option<&str>, bool)]) -> TempPath {
+    let output = TempPath::new("muxed.mkv");
+    let mut octx = ffmpeg::format::output(output.path()).unwrap();
+    let mut ictxs = Vec::new();
+    for (input, language, default) in inputs {
+        let ictx = ffmpeg::format::input(input.path()).unwrap();
+        let stream = ictx.stream(0).unwrap();
+        let mut ost = octx
+            .add_stream(ffmpeg::encoder::find(ffmpeg::codec::Id::None))
+            .unwrap();
+        ost.set_parameters(stream.parameters());
+        let mut metadata = Dictionary::new();
+        if let Some(language) = language {
+            metadata.set("language", language);
+        }
+        ost.set_metadata(metadata);
+        let disposition = match default {
+            true => Disposition::DEFAULT,
+            false => Disposition::empty(),
+        };
+        unsafe {
+            // let the muxer pick the tag of the codec
+            (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+            (*ost.as_mut_ptr()).disposition = disposition.bits();
+        }
+        let time_base = stream.time_base();
+        ictxs.push((ictx, time_base));
+    }
+    octx.write_header().unwrap();
+    for (index, (ictx, time_base)) in ictxs.iter_mut().enumerate() {
+        let output_time_base = octx.stream(index).unwrap().time_base();
+        for (stream, mut packet) in ictx.packets() {
+            if stream.index() != 0 {
+                continue;
+            }
+            packet.rescale_ts(*time_base, output_time_base);
+            packet.set_stream(index);
+            packet.set_position(-1);
+            packet.write_interleaved(&mut octx).unwrap();
+        }
+    }
+    octx.write_trailer().unwrap();
+    output
+}
+
+/// Writes a 1 s video (see `video`) with an English SubRip stream, its cues are `Hello` (in
+/// italics) from 0.1 s to 0.5 s and `Two` and `lines` from 0.61 s to 0.9 s.
+pub fn subtitled_video() -> TempPath {
+    let video = video(25);
+    let srt = TempPath::new("cues.srt");
+    std::fs::write(
+        srt.path(),
+        "1\n00:00:00,100 --> 00:00:00,500\n<i>Hello</i>\n\n\
+         2\n00:00:00,610 --> 00:00:00,900\nTwo\nlines\n",
+    )
+    .unwrap();
+    mux(&[(&video, None, true), (&srt, Some("eng"), false)])
+}
@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+/// Part of an input from `start` to `end` (the end of the input if it's `None`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimeRange {
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+impl TimeRange {
+    pub fn new(start: Duration, end: Option<Duration>) -> Self {
+        Self { start, end }
+    }
+
+    /// The range of `duration` from `start` on.
+    pub fn with_duration(start: Duration, duration: Duration) -> Self {
+        Self::new(start, Some(start + duration))
+    }
+
+    /// Whether the range is the whole input.
+    pub fn is_full(&self) -> bool {
+        self.start.is_zero() && self.end.is_none()
+    }
+
+    /// Whether `time` (relative to the start of the input) is in the range.
+    pub fn contains(&self, time: Duration) -> bool {
+        time >= self.start && self.end.is_none_or(|end| time < end)
+    }
+
+    /// Length of the range in an input of `total` length, `total` is ignored if it's zero
+    /// (unknown).
+    pub fn length(&self, total: Duration) -> Duration {
+        let end = match (self.end, total.is_zero()) {
+            (Some(end), true) => end,
+            (Some(end), false) => end.min(total),
+            (None, _) => total,
+        };
+        end.saturating_sub(self.start)
+    }
+
+    pub fn start_micros(&self) -> i64 {
+        self.start.as_micros() as i64
+    }
+
+    /// End relative to the start of the range in microseconds.
+    pub fn length_micros(&self) -> Option<i64> {
+        self.end
+            .map(|end| end.saturating_sub(self.start).as_micros() as i64)
+    }
+}
+
+/// Parses a time like `90`, `1:30`, `1:02:03` or `12.5` (seconds can have a fraction).
+pub fn parse_time(time: &str) -> Option<Duration> {
+    // at most hours
+    if time.matches(':').count() > 2 {
+        return None;
+    }
+    let mut parts = time.rsplit(':');
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+    let mut total = seconds;
+    for (part, factor) in parts.zip([60.0, 3600.0]) {
+        let value: u32 = part.parse().ok()?;
+        total += value as f64 * factor;
+    }
+    // too long for a `Duration`
+    Duration::try_from_secs_f64(total).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_times() {
+        assert_eq!(parse_time("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_time("1:30"), Some(Duration::from_secs(90)));
+        assert_eq!(
+            parse_time("1:02:03.5"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(parse_time("0.25"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_time("1:2:3:4"), None);
+        assert_eq!(parse_time("-3"), None);
+        assert_eq!(parse_time("1e300"), None);
+        assert_eq!(parse_time("a:10"), None);
+        assert_eq!(parse_time(""), None);
+    }
+
+    #[test]
+    fn range_length() {
+        let secs = Duration::from_secs;
+        let range = TimeRange::with_duration(secs(10), secs(5));
+        assert_eq!(range.length(secs(60)), secs(5));
+        assert_eq!(range.length(secs(12)), secs(2));
+        assert_eq!(range.length(Duration::ZERO), secs(5));
+        assert_eq!(TimeRange::new(secs(10), None).length(secs(60)), secs(50));
+        assert!(range.contains(secs(10)));
+        assert!(!range.contains(secs(15)));
+        assert!(TimeRange::default().is_full());
+    }
+}
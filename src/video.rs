@@ -1,50 +1,35 @@
 extern crate ffmpeg_next as ffmpeg;
 
-use crate::Pixels;
+use crate::Frame;
+use crate::animation::{AnimationFormat, AnimationOptions, AnimationWriter};
+use crate::audio::{self, AudioBackend, AudioSink};
+use crate::clock::{AudioClock, Clock, FrameStats, Pacer, PlaybackClock, Schedule};
+use crate::controls::{self, AudioControls, Command, Keyboard};
+use crate::convert::AudioMode;
+use crate::encode::{self, EncoderOptions, VideoWriter};
+use crate::streams::{self, StreamError, StreamSelector};
+use crate::subtitles::{self, SubtitleDecoder, SubtitleSource, Subtitles};
+use crate::trim::TimeRange;
+use crate::visualize::{self, AudioView, Visualization, Visualizer};
 use crate::wait_for_terminal_scale;
 
-use cpal::SampleFormat;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-
 use ffmpeg::format::{Pixel, Sample as FFmpegSample, context::Input, sample::Type as SampleType};
 use ffmpeg::media::Type as MediaType;
 use ffmpeg::software::scaling::context::Context;
 use ffmpeg::util::frame::{self, Audio, Video};
+use ffmpeg::{ChannelLayout, Rational, Rescale};
 
 use ringbuf::RingBuffer;
 
+use imageproc::image::RgbImage;
+
 use std::collections::HashMap;
 use std::fs::{self};
-use std::time::SystemTime;
-
-trait SampleFormatConversion {
-    fn as_ffmpeg_sample(&self) -> FFmpegSample;
-}
-
-impl SampleFormatConversion for SampleFormat {
-    fn as_ffmpeg_sample(&self) -> FFmpegSample {
-        match self {
-            Self::I16 => FFmpegSample::I16(SampleType::Packed),
-            Self::F32 => FFmpegSample::F32(SampleType::Packed),
-            f => {
-                panic!("ffmpeg resampler doesn't support {f}")
-            }
-        }
-    }
-}
-
-fn write_audio(
-    data: &mut [f32],
-    samples: &mut ringbuf::Consumer<f32>,
-    _cbinfo: &cpal::OutputCallbackInfo,
-) {
-    for d in data {
-        match samples.pop() {
-            Some(sample) => *d = sample,
-            None => *d = 0.0,
-        }
-    }
-}
+use std::io;
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 pub fn packed<T: frame::audio::Sample>(frame: &frame::Audio) -> &[T] {
     if !frame.is_packed() {
@@ -63,230 +48,391 @@ pub fn packed<T: frame::audio::Sample>(frame: &frame::Audio) -> &[T] {
     }
 }
 
+/// Wraps the first plane of the given `RGB24` video frame without copying it.
+///
+/// The rows of ffmpeg frames are padded to `frame.stride(0)` bytes, which is why the plane can't
+/// be read as packed `RGB` data.
+pub fn as_frame(frame: &Video) -> Frame<'_> {
+    assert_eq!(frame.format(), Pixel::RGB24, "video frame is not RGB24");
+    Frame::from_raw(
+        frame.width() as usize,
+        frame.height() as usize,
+        frame.stride(0),
+        frame.data(0),
+    )
+    .expect("plane is smaller than stride * height")
+}
+
+/// Draws the `visualization` of the `samples` over the bottom of the `RGB24` `frame`.
+fn draw_overlay(frame: &mut Video, visualization: Visualization, samples: &[f32]) {
+    let mut pixels = as_frame(frame).into_owned();
+    visualization.draw(&mut pixels, samples);
+    let stride = frame.stride(0);
+    let data = frame.data_mut(0);
+    for (y, row) in pixels.rows().enumerate() {
+        data[y * stride..y * stride + row.len()].copy_from_slice(row);
+    }
+}
+
+/// Timing of a decoded video frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTime {
+    /// Presentation timestamp in `time_base` units, relative to the start of the input.
+    pub pts: i64,
+    pub time_base: Rational,
+    /// Average frame rate of the stream (e.g. 30000/1001), frames of variable frame rate videos
+    /// are only spaced by it on average.
+    pub frame_rate: Rational,
+    /// Duration of the input in microseconds.
+    pub duration_micros: i64,
+}
+
+impl FrameTime {
+    /// The presentation timestamp in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.pts as f64 * f64::from(self.time_base)
+    }
+
+    /// Progress through the input (`0.0..=1.0` if the duration is known).
+    pub fn progress(&self) -> f64 {
+        match self.duration_micros {
+            d if d > 0 => self.seconds() * 1_000_000.0 / d as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Start time of the input in microseconds, `0` if it isn't known.
+///
+/// Timestamps of all streams are relative to it, so they stay in sync.
+pub fn start_time(ictx: &Input) -> i64 {
+    match unsafe { (*ictx.as_ptr()).start_time } {
+        ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+        start => start,
+    }
+}
+
+/// Average frame rate of the video `stream`, 24 fps if it isn't known (or implausible).
+fn stream_frame_rate(stream: &ffmpeg::format::stream::Stream) -> Rational {
+    [stream.avg_frame_rate(), stream.rate()]
+        .into_iter()
+        .find(|rate| {
+            rate.numerator() > 0 && rate.denominator() > 0 && f64::from(*rate) <= 1000.0
+        })
+        .unwrap_or(Rational(24, 1))
+}
+
+/// What is taken from an input, for playing and rendering it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InputOptions {
+    /// Part of the input that's used.
+    pub range: TimeRange,
+    /// What's shown of inputs without video (like MP3 or FLAC files).
+    pub visualizer: AudioView,
+    /// Video stream that's played, see `streams::list_streams`.
+    pub video: StreamSelector,
+    /// Audio stream that's played (or visualized).
+    pub audio: StreamSelector,
+    /// Subtitles shown over the bottom of the video (burned in when rendering to a file).
+    pub subtitles: SubtitleSource,
+}
+
+/// The subtitles of the `input` options, the cues of a stream are added as they are decoded. The
+/// input is played (or rendered) without the subtitles of a file that can't be loaded.
+fn load_subtitles(input: &InputOptions) -> Option<Subtitles> {
+    match &input.subtitles {
+        SubtitleSource::None => None,
+        SubtitleSource::Stream(_) => Some(Subtitles::default()),
+        SubtitleSource::File(file) => Subtitles::from_file(file)
+            .map_err(|err| eprintln!("Playing without subtitles, {err}"))
+            .ok(),
+    }
+}
+
+/// Checks that the input has the streams the `input` options select. The audio of inputs without
+/// video is visualized, so one of them is enough if they aren't selected.
+fn check_streams(ictx: &Input, input: &InputOptions) -> Result<(), StreamError> {
+    let mut selectors = vec![
+        (MediaType::Video, &input.video),
+        (MediaType::Audio, &input.audio),
+    ];
+    if let SubtitleSource::Stream(selector) = &input.subtitles {
+        selectors.push((MediaType::Subtitle, selector));
+    }
+    for (medium, selector) in selectors {
+        if *selector != StreamSelector::Best
+            && streams::select_stream(ictx, medium, selector).is_none()
+        {
+            return Err(StreamError::NotFound(medium, selector.clone()));
+        }
+    }
+    let has_video = streams::select_stream(ictx, MediaType::Video, &input.video).is_some();
+    if !has_video && streams::select_stream(ictx, MediaType::Audio, &input.audio).is_none() {
+        return Err(StreamError::NotFound(MediaType::Audio, input.audio.clone()));
+    }
+    Ok(())
+}
+
+/// Time of the frame relative to the start of the input, like the cues of subtitles.
+fn subtitle_time(time: &FrameTime, range: &TimeRange) -> Duration {
+    range.start + Duration::try_from_secs_f64(time.seconds()).unwrap_or_default()
+}
+
+/// Options of the playback of `draw_with`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaybackOptions {
+    /// Seconds frames are shown before their timestamp (negative: after it), to compensate for
+    /// the latency of the terminal.
+    pub av_offset: f64,
+    /// Keyboard controls (if stdin is a terminal): space pauses, the arrow keys seek by 5 and 60
+    /// seconds, `[` and `]` change the speed, `+` and `-` the volume, `m` mutes and `q` quits.
+    pub controls: bool,
+    /// Show the time, duration, speed, volume and state of the playback beneath the video.
+    pub status_bar: bool,
+    pub input: InputOptions,
+    pub audio: AudioBackend,
+    /// Volume the audio starts at, `1.0` is the original volume (up to `controls::MAX_VOLUME`).
+    pub volume: f32,
+    /// Visualization of the audio drawn over the bottom of the video.
+    pub overlay: Option<Visualization>,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            av_offset: 0.0,
+            controls: true,
+            status_bar: false,
+            input: InputOptions::default(),
+            audio: AudioBackend::default(),
+            volume: 1.0,
+            overlay: None,
+        }
+    }
+}
+
+/// Plays the video at `path` in the terminal, see `draw_with`.
 pub fn draw(
     path: &str,
     scale_algorithm: ffmpeg_next::software::scaling::flag::Flags,
     max_width: f64,
-) {
-    play(
-        path,
+) -> Result<(), StreamError> {
+    draw_with(path, scale_algorithm, max_width, &PlaybackOptions::default())
+}
+
+/// Plays the video at `path` in the terminal, the frames are synchronized to the audio (or the
+/// wall clock if there is no audio) and dropped if they are late.
+///
+/// See `PlaybackOptions` for the keyboard controls. Fails if the input can't be opened or doesn't
+/// have the streams the options select.
+pub fn draw_with(
+    path: &str,
+    scale_algorithm: ffmpeg_next::software::scaling::flag::Flags,
+    max_width: f64,
+    options: &PlaybackOptions,
+) -> Result<(), StreamError> {
+    let mut height = 0;
+    let play_options = PlayOptions {
         scale_algorithm,
-        Some(max_width),
-        false,
-        true,
-        Pixel::RGB24,
-        |frame, _, _| {
-            let pixels = frame.data(0);
-            let pixels = crate::format_pixels(pixels, frame.width() as u16);
-            let height = pixels.len();
-            crate::draw(pixels);
-            print!("\x1b[{height}A");
-        },
+        max_width: Some(max_width),
+        disable_audio: false,
+        fit_terminal: true,
+        playback: Some(options),
+        format: Pixel::RGB24,
+        input: &options.input,
+    };
+    let stats = play(path, &play_options, |frame, _, subtitles| {
+        let pixels = as_frame(&frame);
+        crate::draw(&pixels);
+        height = pixels.height;
+        let _ = print_subtitles(&mut io::stdout(), subtitles, pixels.width * 2);
+        print!("\x1b[{height}A");
+        ControlFlow::Continue(())
+    })?;
+    println!(
+        "\x1b[{height}B\x1b[2KDropped {} of {} frames",
+        stats.dropped,
+        stats.presented + stats.dropped
     );
+    Ok(())
+}
+
+/// Number of frames that can be queued between the stages of `draw_to_file`.
+const QUEUE_SIZE: usize = 8;
+
+type Times = HashMap<&'static str, Vec<u128>>;
+
+fn add_time(times: &mut Times, label: &'static str, since: SystemTime) {
+    let ns = SystemTime::now().duration_since(since).unwrap().as_nanos();
+    times.entry(label).or_default().push(ns);
 }
 
 /// `max_width` is the `max_width` of the video that get's converted to images that then get
 /// converted back to a video...
+///
+/// Decoding, rendering and encoding run on separate threads, connected by bounded channels. All
+/// frames are encoded by one encoder (configured with `encoder_options`), the container is
+/// guessed from the extension of `dst`. Only the range of the `input` options is rendered, the
+/// audio is trimmed to it as well. Inputs without video are rendered as the visualizer of the
+/// `input` options, its subtitles are burned in. Fails like `draw_with`, before anything is
+/// rendered, or with `StreamError::Encode` if the video can't be encoded.
 pub fn draw_to_file(
     src: &str,
     dst: &str,
     font: &ab_glyph::FontRef<'_>,
     scale_algorithm: ffmpeg_next::software::scaling::flag::Flags,
     max_width: Option<f64>,
-) {
+    encoder_options: &EncoderOptions,
+    input: &InputOptions,
+) -> Result<(), StreamError> {
     let id = rand::random::<u32>();
-    let mut counter = 0;
     let title = src.split("/").last().unwrap_or(&src);
 
     let root = "tmp";
 
     fs::create_dir_all(root).unwrap();
 
-    let tmp_video = format!("{root}/{id}.video.mp4");
-
-    let mut mp4muxer = minimp4::Mp4Muxer::new(fs::File::create(&tmp_video).unwrap());
-
-    // todo: make get the correct size.
-    mp4muxer.init_video(1280, 720, false, dst);
+    let extension = encode::output_extension(dst, encoder_options);
+    let tmp_video = format!("{root}/{id}.video.{extension}");
 
-    let mut loading_frame = Vec::new();
-    let started = SystemTime::now();
-    let mut times: HashMap<&str, Vec<u128>> = HashMap::new();
+    let renderer = crate::image::Renderer::new(font, &crate::image::RenderOptions::default());
+    let ictx = ffmpeg::format::input(&src).map_err(StreamError::Input)?;
+    check_streams(&ictx, input)?;
 
-    let mref = &mut mp4muxer;
-    let moved_lframe = &mut loading_frame;
-    let mut add_time = |label: &'static str, ns: u128| {
-        if let Some(t) = &mut times.get_mut(label) {
-            t.push(ns);
-        } else {
-            let _ = &mut times.insert(label, Vec::from([ns]));
-        }
-    };
-    play(
-        src,
+    let play_options = PlayOptions {
         scale_algorithm,
         max_width,
-        true,
-        false,
-        Pixel::RGB24,
-        move |frame, frame_rate, duration_micros| {
-            let pixels = crate::format_pixels(frame.data(0), frame.width() as u16);
+        disable_audio: true,
+        fit_terminal: false,
+        playback: None,
+        format: Pixel::RGB24,
+        input,
+    };
+
+    let (frames_tx, frames_rx) =
+        mpsc::sync_channel::<(Frame<'static>, FrameTime, Vec<String>)>(QUEUE_SIZE);
+    let (images_tx, images_rx) = mpsc::sync_channel::<(RgbImage, FrameTime)>(QUEUE_SIZE);
+
+    let (mut times, loading_height, encoded) = thread::scope(|scope| {
+        // decode
+        scope.spawn(move || {
+            // the streams are checked already
+            let _ = play(src, &play_options, move |frame, time, subtitles| {
+                let pixels = as_frame(&frame).into_owned();
+                let subtitles = subtitles.iter().map(|line| line.to_string()).collect();
+                // the receiver only hangs up if rendering failed
+                match frames_tx.send((pixels, time, subtitles)) {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(_) => ControlFlow::Break(()),
+                }
+            });
+        });
+
+        // render
+        let render = scope.spawn(move || {
+            let mut times = Times::new();
+            let mut loading_frame = Frame::new(0, 0);
+            let started = SystemTime::now();
+            for (counter, (pixels, time, subtitles)) in frames_rx.into_iter().enumerate() {
+                if counter % 10 == 0 {
+                    let s = SystemTime::now();
+                    let (w, h) = term_size::dimensions().unwrap_or((50, 0));
+                    let w = 52.min(w) - 2;
+                    let h = 28.min(h);
+                    let h = if h == 0 && h > 6 { None } else { Some(h - 5) };
+                    loading_frame =
+                        crate::downscale_pixels(&pixels, w, h).unwrap_or_else(|| pixels.clone());
+
+                    let decimal = time.progress() as f32;
+
+                    print_progress(&loading_frame, title, counter, decimal, started);
+                    add_time(&mut times, "loading", s);
+                }
 
-            if counter % 10 == 0 {
                 let s = SystemTime::now();
-                let (w, h) = term_size::dimensions().unwrap_or((50, 0));
-                let w = 52.min(w) - 2;
-                let h = 28.min(h);
-                let h = if h == 0 && h > 6 { None } else { Some(h - 5) };
-                *moved_lframe = crate::downscale_pixels(&pixels, w, h).unwrap_or(pixels.clone());
-
-                let frames = frame_rate * (duration_micros / 1_000_000) as f32;
-                let decimal = counter as f32 / frames;
-
-                let frame_color = match decimal {
-                    d if d < 0.5 => 31,
-                    d if d < 1.0 => 33,
-                    _ => 32,
-                };
-                let fc = format!("\x1b[{frame_color}m");
-
-                let f = |pixels: &Pixels, dec_percentage: f32| {
-                    let width = pixels[0].len();
-                    let max_count = ((pixels.len() * width) as f32 * dec_percentage) as usize;
-                    let mut count: usize = 0;
-                    println!(
-                        "\x1b[2K{fc}╭{:─^1$}╮",
-                        format!(" \x1b[1;{frame_color}m{title}{fc} "),
-                        width * 2 + 12
-                    );
-                    for row in pixels {
-                        // ╭───╮
-                        // ├───┤
-                        // ╰───╯
-                        let mut left = row.len();
-                        print!("\x1b[2K{fc}│");
-                        for (r, g, b) in row {
-                            if count >= max_count {
-                                break;
-                            }
-                            let l = crate::get_lightness(*r, *g, *b);
-                            let s = crate::symbol(l);
-                            let s = if s == ' ' { '.' } else { s };
-                            print!("\x1b[38;2;{r};{g};{b}m{s}{s}");
-                            count += 1;
-                            left -= 1;
-                        }
-                        print!("{: <w$}{fc}│", "", w = left * 2);
-                        println!();
-                    }
-                    let secs_since = SystemTime::now().duration_since(started).unwrap().as_secs();
-                    let secs_since = if secs_since == 0 { 1 } else { secs_since };
-                    let fps = if counter == 0 {
-                        1.0 / secs_since as f32
-                    } else {
-                        counter as f32 / secs_since as f32
-                    };
-                    let fps = format!("{fps:.1}");
-                    let info = if dec_percentage < 1.0 {
-                        format!(
-                            "\x1b[1;32m{}% {:>10}s {:>10} fps",
-                            (dec_percentage * 100.0).round(),
-                            secs_since,
-                            fps,
-                        )
-                    } else {
-                        format!("\x1b[1;32mDone! (in {}s, fps: {fps})", secs_since)
-                    };
-                    let info_title = format!(" \x1b[1;{frame_color}minfo{fc} ");
-                    println!("\x1b[2K{fc}├{:─^w$}┤", info_title, w = width * 2 + 12);
-                    println!("\x1b[2K{fc}│{: ^w$}{fc}│", info, w = width * 2 + 7);
-                    println!("\x1b[2K{fc}╰{:─<w$}{fc}╯", "", w = width * 2);
-                    print!("\x1b[{}A\x1b[0m", moved_lframe.len() + 4);
-                };
+                // get frame rgb
+                let mut image = renderer.render(&pixels);
+                if !subtitles.is_empty() {
+                    let lines: Vec<_> = subtitles.iter().map(String::as_str).collect();
+                    subtitles::burn_in(&mut image, font, renderer.layout(), &lines);
+                }
+                add_time(&mut times, "get ascii frame rgb", s);
 
-                f(&moved_lframe, decimal);
-                add_time(
-                    "loading",
-                    SystemTime::now().duration_since(s).unwrap().as_nanos(),
-                );
+                if images_tx.send((image, time)).is_err() {
+                    break;
+                }
             }
+            (times, loading_frame.height)
+        });
 
-            let s = SystemTime::now();
-            // get frame rgb
-            let tmp_img = crate::image::get_image_buf(font, &pixels);
-            let height = tmp_img.height();
-            let width = tmp_img.width();
-            let rgb = tmp_img.as_raw();
-            add_time(
-                "get ascii frame rgb",
-                SystemTime::now().duration_since(s).unwrap().as_nanos(),
-            );
-
-            let s = SystemTime::now();
-            // convert the rgb values to yuv
-            let mut encoder = openh264::encoder::Encoder::new().expect("Couldn't create encoder");
-            let rgb_source =
-                openh264::formats::RgbSliceU8::new(&rgb, (width as usize, height as usize));
-            let yuv = openh264::formats::YUVBuffer::from_rgb_source(rgb_source);
-
-            let bitstream = encoder.encode(&yuv).unwrap();
-
-            let mut buf = Vec::new();
-            bitstream.write_vec(&mut buf);
-
-            // let mut my_buf = Vec::new();
-            // let mut ys = Vec::new();
-            // let mut us = Vec::new();
-            // let mut vs = Vec::new();
-            // for rgb in rgb.chunks(3) {
-            //     let (y, u, v) = crate::rgb_to_yuv(rgb[0], rgb[1], rgb[2]);
-            //     ys.push(y);
-            //     us.push(u);
-            //     vs.push(v);
-            // }
-
-            // let quarter = |mut values: Vec<u8>| {
-            //     values.drain(..)
-            //         .enumerate()
-            //         .fold(Vec::new(), |mut acc, (i, v)| {
-            //             if i % 4 == 0 {
-            //                 acc.push(v);
-            //             }
-            //             acc
-            //         })
-            // };
-            // my_buf.append(&mut ys);
-            // let mut us = quarter(us);
-            // my_buf.append(&mut us);
-            // let mut vs = quarter(vs);
-            // my_buf.append(&mut vs);
-
-            add_time(
-                "convert rgb to yuv",
-                SystemTime::now().duration_since(s).unwrap().as_nanos(),
-            );
+        // encode
+        let mut times = Times::new();
+        let mut encode_frames = || {
+            let mut writer = None;
+            let mut size = None;
+            // returning early hangs up on the render thread, which then hangs up on the decoder
+            for (image, time) in images_rx {
+                // the writer is created with the size of the first frame
+                let (width, height) = *size.get_or_insert_with(|| {
+                    encode::output_size(image.width(), image.height(), encoder_options.resolution)
+                });
+                let image = match encoder_options.resolution {
+                    Some(_) => encode::letterbox(image, width, height),
+                    None => encode::pad(image, width, height),
+                };
+                let rgb = image.as_raw();
 
-            let s = SystemTime::now();
+                let s = SystemTime::now();
+                // convert the rgb values to yuv, encode them and write them to the video
+                let writer = match &mut writer {
+                    Some(writer) => writer,
+                    None => writer.insert(VideoWriter::new(
+                        &tmp_video,
+                        width,
+                        height,
+                        time.frame_rate,
+                        time.time_base,
+                        encoder_options,
+                    )?),
+                };
+                // the timestamps of the source, so variable frame rates and the audio stay in sync
+                writer.write(rgb, width as usize, height as usize, time.pts)?;
+                add_time(&mut times, "encode frame", s);
+            }
+            match writer {
+                Some(writer) => writer.finish(),
+                None => Ok(()),
+            }
+        };
+        let encoded = encode_frames();
 
-            // write the resulting frame to the final video
-            mref.write_video_with_fps(&buf, frame_rate.round() as u32);
-            add_time(
-                "write to video",
-                SystemTime::now().duration_since(s).unwrap().as_nanos(),
-            );
-            counter += 1;
-        },
-    );
-    mp4muxer.close();
+        let (render_times, loading_height) = render.join().expect("render thread panicked");
+        times.extend(render_times);
+        (times, loading_height, encoded)
+    });
+    if let Err(err) = encoded {
+        // the video is unusable without its trailer
+        let _ = fs::remove_file(&tmp_video);
+        return Err(StreamError::Encode(err));
+    }
 
-    println!(
-        "\x1b[{}BConvert file, so its smaler",
-        loading_frame.len() + 4
-    );
-    crate::convert::convert(src, &tmp_video, dst);
-    println!("Remove tmp video file: {tmp_video}");
-    if let Err(err) = fs::remove_file(&tmp_video) {
-        eprintln!("{err}");
+    println!("\x1b[{}BAdd the audio of {src}", loading_height + 4);
+    match crate::convert::convert_with(
+        src,
+        &tmp_video,
+        dst,
+        AudioMode::default(),
+        &input.range,
+        &input.audio,
+    ) {
+        Ok(()) => {
+            println!("Remove tmp video file: {tmp_video}");
+            if let Err(err) = fs::remove_file(&tmp_video) {
+                eprintln!("{err}");
+            }
+        }
+        // keep the video without audio
+        Err(err) => eprintln!("Couldn't add the audio of {src} to {tmp_video}: {err}"),
     }
 
     println!("times:");
@@ -297,187 +443,823 @@ pub fn draw_to_file(
             (value.iter().sum::<u128>() / len) / 1_000_000
         );
     }
+    Ok(())
 }
 
-fn play<F>(
-    path: &str,
+/// Renders the video (or animated image) at `src` into the animated image `dst`, a GIF, APNG or
+/// animated WebP (picked by the extension of `dst`). The audio is dropped.
+pub fn draw_to_animation(
+    src: &str,
+    dst: &str,
+    font: &ab_glyph::FontRef<'_>,
+    scale_algorithm: ffmpeg_next::software::scaling::flag::Flags,
+    max_width: Option<f64>,
+    options: &AnimationOptions,
+) -> io::Result<()> {
+    let format = AnimationFormat::from_path(dst).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{dst} is not a gif, apng or webp file"),
+        )
+    })?;
+    let renderer = crate::image::Renderer::new(font, &crate::image::RenderOptions::default());
+
+    let mut writer: Option<AnimationWriter> = None;
+    let mut result = Ok(());
+    let mut counter = 0;
+    let play_options = PlayOptions {
+        scale_algorithm,
+        max_width,
+        disable_audio: true,
+        fit_terminal: false,
+        playback: None,
+        format: Pixel::RGB24,
+        input: &InputOptions::default(),
+    };
+    play(src, &play_options, |frame, time, _| {
+        if let Some(max_duration) = options.max_duration {
+            if time.seconds() >= max_duration.as_secs_f64() {
+                return ControlFlow::Break(());
+            }
+        }
+        counter += 1;
+        if (counter - 1) % options.frame_step.max(1) != 0 {
+            return ControlFlow::Continue(());
+        }
+
+        let image = renderer.render(&as_frame(&frame));
+        let writer = match &mut writer {
+            Some(writer) => writer,
+            None => {
+                let (width, height) = image.dimensions();
+                let new = AnimationWriter::new(
+                    dst,
+                    format,
+                    width,
+                    height,
+                    time.frame_rate,
+                    time.time_base,
+                    options,
+                );
+                match new {
+                    Ok(new) => writer.insert(new),
+                    Err(err) => {
+                        result = Err(err);
+                        return ControlFlow::Break(());
+                    }
+                }
+            }
+        };
+        match writer.write(image, time.pts) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(err) => {
+                result = Err(err);
+                ControlFlow::Break(())
+            }
+        }
+    })
+    .map_err(io::Error::other)?;
+    result?;
+    match writer {
+        Some(writer) => writer.finish(),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{src} has no frames"),
+        )),
+    }
+}
+
+/// Prints the (downscaled) `frame` with the progress of `draw_to_file`, and moves the cursor back
+/// up.
+fn print_progress(frame: &Frame, title: &str, counter: usize, decimal: f32, started: SystemTime) {
+    let frame_color = match decimal {
+        d if d < 0.5 => 31,
+        d if d < 1.0 => 33,
+        _ => 32,
+    };
+    let fc = format!("\x1b[{frame_color}m");
+
+    let width = frame.width;
+    let max_count = ((frame.height * width) as f32 * decimal) as usize;
+    let mut count: usize = 0;
+    println!(
+        "\x1b[2K{fc}╭{:─^1$}╮",
+        format!(" \x1b[1;{frame_color}m{title}{fc} "),
+        width * 2 + 12
+    );
+    for y in 0..frame.height {
+        // ╭───╮
+        // ├───┤
+        // ╰───╯
+        let mut left = width;
+        print!("\x1b[2K{fc}│");
+        for (r, g, b) in frame.row_pixels(y) {
+            if count >= max_count {
+                break;
+            }
+            let s = crate::CHARS[crate::symbol_index(r, g, b)];
+            let s = if s == ' ' { '.' } else { s };
+            print!("\x1b[38;2;{r};{g};{b}m{s}{s}");
+            count += 1;
+            left -= 1;
+        }
+        print!("{: <w$}{fc}│", "", w = left * 2);
+        println!();
+    }
+    let secs_since = SystemTime::now().duration_since(started).unwrap().as_secs();
+    let secs_since = if secs_since == 0 { 1 } else { secs_since };
+    let fps = if counter == 0 {
+        1.0 / secs_since as f32
+    } else {
+        counter as f32 / secs_since as f32
+    };
+    let fps = format!("{fps:.1}");
+    let info = if decimal < 1.0 {
+        format!(
+            "\x1b[1;32m{}% {:>10}s {:>10} fps",
+            (decimal * 100.0).round(),
+            secs_since,
+            fps,
+        )
+    } else {
+        format!("\x1b[1;32mDone! (in {}s, fps: {fps})", secs_since)
+    };
+    let info_title = format!(" \x1b[1;{frame_color}minfo{fc} ");
+    println!("\x1b[2K{fc}├{:─^w$}┤", info_title, w = width * 2 + 12);
+    println!("\x1b[2K{fc}│{: ^w$}{fc}│", info, w = width * 2 + 7);
+    println!("\x1b[2K{fc}╰{:─<w$}{fc}╯", "", w = width * 2);
+    print!("\x1b[{}A\x1b[0m", frame.height + 4);
+}
+
+/// How `play` decodes the input.
+#[derive(Debug, Clone, Copy)]
+struct PlayOptions<'a> {
     scale_algorithm: ffmpeg_next::software::scaling::flag::Flags,
     max_width: Option<f64>,
     disable_audio: bool,
-    fit_termianl: bool,
+    fit_terminal: bool,
+    /// Frames are paced (and can be controlled by the keyboard) if it's set.
+    playback: Option<&'a PlaybackOptions>,
     format: Pixel,
-    mut f: F,
-) where
-    F: FnMut(Video, f32, i64),
+    /// The timestamps of the frames are relative to the start of its range.
+    input: &'a InputOptions,
+}
+
+/// Decodes the frames of the input at `path` and calls `f` with each of them and the lines of the
+/// subtitles shown with it, fails before that if the input doesn't have the selected streams (see
+/// `check_streams`).
+fn play<F>(path: &str, options: &PlayOptions, mut f: F) -> Result<FrameStats, StreamError>
+where
+    F: FnMut(Video, FrameTime, &[&str]) -> ControlFlow<()>,
 {
+    let PlayOptions {
+        scale_algorithm,
+        max_width,
+        disable_audio,
+        fit_terminal,
+        playback,
+        format,
+        input,
+    } = *options;
+    let range = input.range;
+
     // new input ctx
-    let mut ictx = ffmpeg::format::input(path).expect("Couldn't open file");
-    let duration_micros = ictx.duration();
+    let mut ictx = ffmpeg::format::input(path).map_err(StreamError::Input)?;
+    check_streams(&ictx, input)?;
+    // the cues of a subtitle stream are decoded as its packets are read
+    let mut subtitle_decoder = match &input.subtitles {
+        SubtitleSource::Stream(selector) => Some(SubtitleDecoder::new(&ictx, selector)?),
+        _ => None,
+    };
+    let mut subtitles = load_subtitles(input);
+    let total = Duration::from_micros(ictx.duration().max(0) as u64);
+    let duration_micros = range.length(total).as_micros() as i64;
+    // timestamps are relative to the start of the range
+    let start_micros = start_time(&ictx) + range.start_micros();
+    if !range.start.is_zero() {
+        // to the keyframe before the start, the frames up to it are decoded but not shown (all of
+        // them if the input can't seek, like a pipe)
+        let _ = ictx.seek(start_micros, ..start_micros);
+    }
 
     // create buffer to store audio data
     let buffer = RingBuffer::<f32>::new(2usize.pow(13));
     let (mut producer, consumer) = buffer.split();
+    let audio_controls = AudioControls::default();
+    if let Some(playback) = playback {
+        audio_controls.set_volume(playback.volume);
+    }
 
     // get best audio stream index AND creat audio decoder AND create resampler
-    let (mut audio_decoder, mut resampler, mut audio_stream, audio_stream_index) = if disable_audio
-    {
-        (None, None, None, None)
-    } else {
-        get_audio(&mut ictx, consumer)
-    };
+    let (mut audio_decoder, mut resampler, mut audio_sink, audio_stream_index, audio_clock) =
+        if disable_audio {
+            (None, None, None, None, None)
+        } else {
+            let backend = playback.map_or(&AudioBackend::Cpal, |playback| &playback.audio);
+            get_audio(
+                &mut ictx,
+                &input.audio,
+                backend,
+                consumer,
+                audio_controls.clone(),
+            )
+        };
+    let audio_time_base = audio_stream_index
+        .and_then(|index| ictx.stream(index))
+        .map(|stream| stream.time_base());
 
-    // contruct video decoder AND scaler AND get best video stream index
-    let (mut video_decoder, mut scaler, video_stream_index) =
-        get_video(&mut ictx, scale_algorithm, format, max_width);
+    // contruct video decoder (or visualizer) AND scaler AND get best video stream index
+    let (mut video_source, mut scaler, video_stream_index, time_base, frame_rate) =
+        get_video(&mut ictx, scale_algorithm, format, max_width, input);
+    let start = start_micros.rescale(ffmpeg::rescale::TIME_BASE, time_base);
+    let end = range
+        .length_micros()
+        .map(|end| end.rescale(ffmpeg::rescale::TIME_BASE, time_base));
+    // used for frames without a timestamp
+    let frame_duration = 1i64.rescale(frame_rate.invert(), time_base).max(1);
+    let mut next_pts = 0;
+    let status_row = scaler.output().height;
 
-    if fit_termianl {
+    if fit_terminal {
         wait_for_terminal_scale(scaler.output().width * 2, scaler.output().height + 2);
     }
 
-    let mut process_audio_frames = |decoder: &mut ffmpeg::decoder::Audio| {
+    let mut process_audio_frames = |decoder: &mut ffmpeg::decoder::Audio,
+                                    audio_started: &mut bool,
+                                    skip: bool| {
         let mut decoded = Audio::empty();
         if let Some(resampler) = &mut resampler {
             while decoder.receive_frame(&mut decoded).is_ok() {
+                // the audio isn't played at other speeds
+                if skip {
+                    continue;
+                }
+                // timestamp relative to the start of the range in microseconds
+                let micros = match (audio_time_base, decoded.timestamp()) {
+                    (Some(time_base), Some(pts)) => {
+                        Some(pts.rescale(time_base, ffmpeg::rescale::TIME_BASE) - start_micros)
+                    }
+                    _ => None,
+                };
+                let length = (decoded.samples() as i64).rescale(
+                    Rational(1, decoded.rate() as i32),
+                    ffmpeg::rescale::TIME_BASE,
+                );
+                // decoded from before the start (or after the end) of the range
+                if micros.is_some_and(|micros| {
+                    micros + length <= 0 || range.length_micros().is_some_and(|end| micros >= end)
+                }) {
+                    continue;
+                }
+                // the clock starts at the timestamp of the first sample
+                if let (false, Some(clock), Some(micros)) = (*audio_started, &audio_clock, micros) {
+                    clock.set_start(Duration::from_micros(micros.max(0) as u64));
+                }
+                *audio_started = true;
+
                 let mut resampled = Audio::empty();
                 resampler
                     .run(&decoded, &mut resampled)
                     .expect("Input or output changed");
 
                 // There maybe more then one audio stream
-                let both_channels = packed(&resampled);
-
-                while producer.remaining() < both_channels.len() {
-                    std::thread::sleep(std::time::Duration::from_millis(10));
+                let mut both_channels = packed(&resampled);
+                // the samples before the start of the range
+                if let Some(micros) = micros.filter(|micros| *micros < 0) {
+                    let samples = (-micros).rescale(
+                        ffmpeg::rescale::TIME_BASE,
+                        Rational(1, resampled.rate() as i32),
+                    );
+                    let skipped = samples as usize * resampled.channels() as usize;
+                    both_channels = &both_channels[skipped.min(both_channels.len())..];
+                }
+                // and after its end
+                if let (Some(micros), Some(end)) = (micros, range.length_micros()) {
+                    let samples = (end - micros.max(0)).rescale(
+                        ffmpeg::rescale::TIME_BASE,
+                        Rational(1, resampled.rate() as i32),
+                    );
+                    let kept = samples as usize * resampled.channels() as usize;
+                    both_channels = &both_channels[..kept.min(both_channels.len())];
                 }
 
-                producer.push_slice(both_channels);
+                // frames can have more samples than fit into the buffer
+                audio::push_samples(&mut producer, both_channels, &audio_controls);
             }
         }
     };
 
-    let mut process_frames = |decoder: &mut ffmpeg::decoder::Video| {
-        let mut decoded = Video::empty();
-        let fps = match decoder.frame_rate() {
-            Some(fr) => fr.numerator() as f32,
-            None => 24.0,
-        };
-        let fps = if fps > 1000.0 { 24.0 } else { fps };
-        while decoder.receive_frame(&mut decoded).is_ok() {
+    // frames are shown when they are due (by the audio if there is any), late frames are dropped
+    // before they are scaled
+    let mut pacer = playback.map(|_| {
+        let max_lateness = Duration::from_secs_f64(f64::from(frame_rate.invert()));
+        Pacer::new(PlaybackClock::new(audio_clock.clone()), max_lateness)
+    });
+    let av_offset = playback.map_or(0.0, |playback| playback.av_offset);
+    let mut keyboard = playback
+        .filter(|playback| playback.controls)
+        .and_then(|_| Keyboard::new());
+    let status_bar = playback.is_some_and(|playback| playback.status_bar);
+    let overlay = playback.and_then(|playback| playback.overlay);
+    let duration = Duration::from_micros(duration_micros.max(0) as u64);
+    let mut stats = FrameStats::default();
+
+    // a decoded frame that isn't due yet, the controls are handled while it waits
+    let mut pending: Option<(Video, i64)> = None;
+
+    let mut process_frames = |source: &mut VideoSource,
+                              pending: &mut Option<(Video, i64)>,
+                              pacer: Option<&Pacer<PlaybackClock>>,
+                              subtitles: Option<&Subtitles>| {
+        loop {
+            let (decoded, pts) = match pending.take() {
+                Some(pending) => pending,
+                None => {
+                    let mut decoded = Video::empty();
+                    let Some(timestamp) = source.receive_frame(&mut decoded) else {
+                        break;
+                    };
+                    let pts = match timestamp {
+                        Some(pts) => pts - start,
+                        None => next_pts,
+                    };
+                    next_pts = pts + frame_duration;
+                    // decoded from the keyframe before the start, the frame that is shown at the
+                    // start is moved to it
+                    if pts + frame_duration <= 0 {
+                        continue;
+                    }
+                    if end.is_some_and(|end| pts >= end) {
+                        return ControlFlow::Break(());
+                    }
+                    (decoded, pts.max(0))
+                }
+            };
+
+            if let Some(pacer) = pacer {
+                let seconds = pts as f64 * f64::from(time_base) - av_offset;
+                match pacer.wait(Duration::from_secs_f64(seconds.max(0.0))) {
+                    Schedule::Drop => {
+                        stats.dropped += 1;
+                        continue;
+                    }
+                    Schedule::Present(early) if !early.is_zero() => {
+                        *pending = Some((decoded, pts));
+                        return ControlFlow::Continue(());
+                    }
+                    Schedule::Present(_) => {}
+                }
+            }
+            stats.presented += 1;
+
             let mut frame = Video::empty();
             scaler
                 .run(&decoded, &mut frame)
                 .expect("Input or output changed");
-            f(frame, fps, duration_micros);
+            if let Some(visualization) = overlay {
+                let samples = audio_controls.monitor().latest(visualize::FFT_LEN);
+                draw_overlay(&mut frame, visualization, &samples);
+            }
+            let time = FrameTime {
+                pts,
+                time_base,
+                frame_rate,
+                duration_micros,
+            };
+            let lines = subtitles.map_or_else(Vec::new, |subtitles| {
+                subtitles.lines_at(subtitle_time(&time, &range))
+            });
+            f(frame, time, &lines)?;
+            if let (true, Some(pacer)) = (status_bar, pacer) {
+                print_status(pacer.clock(), duration, &audio_controls, status_row);
+            }
         }
+        ControlFlow::Continue(())
     };
 
-    if let Some(audio_stream) = &mut audio_stream {
-        audio_stream.play().unwrap();
+    if let Some(Err(err)) = audio_sink.as_mut().map(|sink| sink.play()) {
+        eprintln!("{err}");
     }
 
-    for (stream, packet) in ictx.packets() {
-        if stream.index() == video_stream_index {
-            video_decoder
-                .send_packet(&packet)
-                .expect("Failed to send video packet");
-            process_frames(&mut video_decoder);
+    let mut audio_started = false;
+    // the input is read to its end, the decoder is drained
+    let mut eof = false;
+    loop {
+        if let (Some(keyboard), Some(pacer)) = (&mut keyboard, &mut pacer) {
+            let clock = pacer.clock_mut();
+            for command in keyboard.commands() {
+                match command {
+                    Command::TogglePause => {
+                        let paused = !clock.is_paused();
+                        clock.set_paused(paused);
+                        if let Some(audio_sink) = &mut audio_sink {
+                            let result = match paused {
+                                true => audio_sink.pause(),
+                                false => audio_sink.play(),
+                            };
+                            if let Err(err) = result {
+                                eprintln!("{err}");
+                            }
+                        }
+                    }
+                    Command::Seek(seconds) => {
+                        let target = (clock.now().as_secs_f64() + seconds as f64)
+                            .clamp(0.0, duration.as_secs_f64());
+                        let target = Duration::from_secs_f64(target);
+                        // to the keyframe before the target, the frames up to it are dropped
+                        let ts = start_micros + target.as_micros() as i64;
+                        if ictx.seek(ts, ..ts).is_ok() {
+                            video_source.flush();
+                            pending = None;
+                            eof = false;
+                            if let Some(audio_decoder) = &mut audio_decoder {
+                                audio_decoder.flush();
+                            }
+                            audio_controls.flush();
+                            audio_started = false;
+                            clock.seek(target);
+                        }
+                    }
+                    Command::Faster | Command::Slower => {
+                        clock.set_speed(controls::next_speed(
+                            clock.speed(),
+                            command == Command::Faster,
+                        ));
+                    }
+                    Command::ToggleMute => audio_controls.set_muted(!audio_controls.is_muted()),
+                    Command::VolumeUp | Command::VolumeDown => {
+                        let step = match command {
+                            Command::VolumeUp => controls::VOLUME_STEP,
+                            _ => -controls::VOLUME_STEP,
+                        };
+                        // in whole steps, so it gets back to 100%
+                        let volume = audio_controls.volume() + step;
+                        audio_controls.set_volume((volume * 10.0).round() / 10.0);
+                    }
+                    Command::Quit => return Ok(stats),
+                }
+            }
+            if clock.is_paused() {
+                if status_bar {
+                    print_status(clock, duration, &audio_controls, status_row);
+                }
+                std::thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+        }
+
+        // the frame that is waiting comes before the next packet
+        if pending.is_some() {
+            let flow = process_frames(
+                &mut video_source,
+                &mut pending,
+                pacer.as_ref(),
+                subtitles.as_ref(),
+            );
+            if flow.is_break() || (eof && pending.is_none()) {
+                return Ok(stats);
+            }
+            if pending.is_some() {
+                continue;
+            }
         }
+
+        let mut packet = ffmpeg::Packet::empty();
+        match packet.read(&mut ictx) {
+            Ok(()) => {}
+            Err(ffmpeg::Error::Eof) if !eof => {
+                // the frames left in the decoder are waited for like the others
+                video_source
+                    .send_eof()
+                    .expect("Failed to send eof (end of file)");
+                eof = true;
+                let flow = process_frames(
+                    &mut video_source,
+                    &mut pending,
+                    pacer.as_ref(),
+                    subtitles.as_ref(),
+                );
+                if flow.is_break() || pending.is_none() {
+                    return Ok(stats);
+                }
+                continue;
+            }
+            Err(ffmpeg::Error::Eof) => return Ok(stats),
+            Err(_) => continue,
+        }
+        let stream = packet.stream();
+        if let (Some(decoder), Some(subtitles)) = (&mut subtitle_decoder, &mut subtitles) {
+            if stream == decoder.index() {
+                if let Some(cue) = decoder.decode(&packet) {
+                    subtitles.insert(cue);
+                }
+            }
+        }
+        // the audio is buffered first, the visualization of inputs without video is drawn from
+        // the same packets and waits until it's played
         if let Some(audio_stream_index) = audio_stream_index {
             if let Some(audio_decoder) = &mut audio_decoder {
-                if stream.index() == audio_stream_index {
+                if stream == audio_stream_index {
                     audio_decoder
                         .send_packet(&packet)
                         .expect("Failed to send audio packet");
-                    process_audio_frames(audio_decoder);
+                    let skip = pacer
+                        .as_ref()
+                        .is_some_and(|pacer| pacer.clock().speed() != 1.0);
+                    process_audio_frames(audio_decoder, &mut audio_started, skip);
                 }
             }
         }
+        if stream == video_stream_index {
+            video_source
+                .send_packet(&packet)
+                .expect("Failed to send video packet");
+            let flow = process_frames(
+                &mut video_source,
+                &mut pending,
+                pacer.as_ref(),
+                subtitles.as_ref(),
+            );
+            if flow.is_break() {
+                return Ok(stats);
+            }
+        }
     }
-    video_decoder
-        .send_eof()
-        .expect("Failed to send eof (end of file)");
-    process_frames(&mut video_decoder);
 }
 
+/// Prints the status bar of the playback in the row `row` beneath the cursor, the cursor stays
+/// where it is.
+fn print_status(clock: &PlaybackClock, duration: Duration, audio: &AudioControls, row: u32) {
+    let state = if clock.is_paused() { "⏸" } else { "▶" };
+    let now = match duration.is_zero() {
+        true => clock.now(),
+        false => clock.now().min(duration),
+    };
+    let mut status = format!(
+        "{state} {} / {}  {}x",
+        controls::format_time(now),
+        controls::format_time(duration),
+        clock.speed()
+    );
+    // the audio is only played at normal speed
+    if audio.is_muted() || (clock.has_audio() && clock.speed() != 1.0) {
+        status.push_str("  muted");
+    } else if audio.volume() != 1.0 {
+        status.push_str(&format!("  {:.0}%", audio.volume() * 100.0));
+    }
+    print!("\x1b[{row}B\r\x1b[2K\x1b[0m{status}\r\x1b[{row}A");
+    let _ = io::Write::flush(&mut io::stdout());
+}
+
+/// Prints the `lines` centered (white on black) over the bottom rows of the frame that was just
+/// drawn to `out`, which is `columns` wide, the cursor is beneath the frame.
+fn print_subtitles(out: &mut impl io::Write, lines: &[&str], columns: usize) -> io::Result<()> {
+    let lines = &lines[..lines.len().min(subtitles::MAX_LINES)];
+    for (i, line) in lines.iter().enumerate() {
+        let up = lines.len() - i;
+        // one space of padding on each side
+        let (offset, line) = subtitles::center(line, columns.saturating_sub(2));
+        write!(out, "\x1b[{up}A\r")?;
+        if offset > 0 {
+            write!(out, "\x1b[{offset}C")?;
+        }
+        write!(
+            out,
+            "\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m {line} \x1b[0m\x1b[{up}B\r"
+        )?;
+    }
+    Ok(())
+}
+
+/// Get the decoder of the audio stream the `selector` selects, with a resampler to the format of
+/// the sink of the `backend`.
+///
+/// If there is no stream (and none is selected) or the sink can't be opened the input is played
+/// without sound.
 fn get_audio(
     ictx: &mut Input,
-    mut consumer: ringbuf::Consumer<f32>,
+    selector: &StreamSelector,
+    backend: &AudioBackend,
+    consumer: ringbuf::Consumer<f32>,
+    controls: AudioControls,
 ) -> (
     Option<ffmpeg::codec::decoder::Audio>,
     Option<ffmpeg::software::resampling::Context>,
-    Option<cpal::Stream>,
+    Option<Box<dyn AudioSink>>,
     Option<usize>,
+    Option<AudioClock>,
 ) {
-    match ictx.streams().best(MediaType::Audio) {
-        Some(audio) => {
-            let host = cpal::default_host();
-
-            let device = host
-                .default_output_device()
-                .expect("No output device available");
-
-            let supported_configs = device
-                .supported_output_configs()
-                .expect("Device disconected")
-                .next()
-                .expect("No supported configs");
-            let audio_config = supported_configs.with_max_sample_rate();
-
-            // create audio decoder
-            let audio_decoder =
-                ffmpeg::codec::context::Context::from_parameters(audio.parameters())
-                    .expect("Couldn't construct audio decoder context")
-                    .decoder()
-                    .audio()
-                    .expect("Couldn't get audio decoder");
-
-            // setup audio resampler
-            let resampler = ffmpeg::software::resampling::Context::get(
-                // in
-                audio_decoder.format(),
-                audio_decoder.channel_layout(),
-                audio_decoder.rate(),
-                // out
-                audio_config.sample_format().as_ffmpeg_sample(),
-                audio_decoder.channel_layout(),
-                audio_config.sample_rate().0,
-            )
-            .expect("Couldn't get resampling context");
-
-            let audio_stream = match audio_config.sample_format() {
-                SampleFormat::F32 => device.build_output_stream(
-                    &audio_config.into(),
-                    move |data: &mut [f32], cbinfo| write_audio(data, &mut consumer, &cbinfo),
-                    |err| {
-                        eprintln!("{err}");
-                    },
-                    None,
-                ),
-                fm => panic!("{fm} is not implemented"),
+    let Some(audio) = streams::select_stream(ictx, MediaType::Audio, selector) else {
+        return (None, None, None, None, None);
+    };
+    let sink = match audio::open_sink(backend, consumer, controls) {
+        Ok(sink) => sink,
+        Err(err) => {
+            eprintln!("Playing without sound, {err}");
+            return (None, None, None, None, None);
+        }
+    };
+
+    let audio_decoder = get_audio_decoder(&audio);
+    // it also down- or upmixes to the channels of the device
+    let resampler = get_resampler(
+        &audio_decoder,
+        ChannelLayout::default(sink.channels() as i32),
+        sink.sample_rate(),
+    );
+
+    let clock = sink.clock().clone();
+    (
+        Some(audio_decoder),
+        Some(resampler),
+        Some(sink),
+        Some(audio.index()),
+        Some(clock),
+    )
+}
+
+fn get_audio_decoder(stream: &ffmpeg::format::stream::Stream) -> ffmpeg::codec::decoder::Audio {
+    ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+        .expect("Couldn't construct audio decoder context")
+        .decoder()
+        .audio()
+        .expect("Couldn't get audio decoder")
+}
+
+/// Get a resampler from the format of the `decoder` to packed `f32` samples with the `layout` at
+/// `rate`.
+fn get_resampler(
+    decoder: &ffmpeg::codec::decoder::Audio,
+    layout: ChannelLayout,
+    rate: u32,
+) -> ffmpeg::software::resampling::Context {
+    let input_layout = match decoder.channel_layout() {
+        layout if layout.is_empty() => ChannelLayout::default(decoder.channels() as i32),
+        layout => layout,
+    };
+    ffmpeg::software::resampling::Context::get(
+        // in
+        decoder.format(),
+        input_layout,
+        decoder.rate(),
+        // out
+        FFmpegSample::F32(SampleType::Packed),
+        layout,
+        rate,
+    )
+    .expect("Couldn't get resampling context")
+}
+
+/// Width of the visualization of inputs without video (if `max_width` allows it), the height
+/// makes it 16:9.
+const VISUALIZER_WIDTH: u32 = 640;
+
+/// Where the frames of `play` come from.
+enum VideoSource {
+    /// The decoder of the video stream.
+    Video(ffmpeg::codec::decoder::Video),
+    /// A visualization of the audio stream, for inputs without video.
+    Audio(AudioVisualizer),
+}
+
+impl VideoSource {
+    fn send_packet(&mut self, packet: &ffmpeg::Packet) -> Result<(), ffmpeg::Error> {
+        match self {
+            Self::Video(decoder) => decoder.send_packet(packet),
+            Self::Audio(audio) => audio.decoder.send_packet(packet),
+        }
+    }
+
+    fn send_eof(&mut self) -> Result<(), ffmpeg::Error> {
+        match self {
+            Self::Video(decoder) => decoder.send_eof(),
+            Self::Audio(audio) => {
+                audio.visualizer.finish();
+                audio.decoder.send_eof()
             }
-            .expect("Failed to build output stream");
-            (
-                Some(audio_decoder),
-                Some(resampler),
-                Some(audio_stream),
-                Some(audio.index()),
-            )
         }
-        None => (None, None, None, None),
+    }
+
+    /// Drops the buffered frames (after seeking).
+    fn flush(&mut self) {
+        match self {
+            Self::Video(decoder) => decoder.flush(),
+            Self::Audio(audio) => {
+                audio.decoder.flush();
+                audio.visualizer.reset();
+            }
+        }
+    }
+
+    /// Get the next frame and its timestamp (if it has one), `None` if there is none until more
+    /// packets are sent.
+    fn receive_frame(&mut self, frame: &mut Video) -> Option<Option<i64>> {
+        match self {
+            Self::Video(decoder) => decoder
+                .receive_frame(frame)
+                .is_ok()
+                .then(|| frame.timestamp()),
+            Self::Audio(audio) => audio.receive_frame(frame).map(Some),
+        }
     }
 }
 
+/// Draws `RGB24` frames of the visualization of an audio stream, their timestamps are in
+/// `1 / VISUALIZER_FPS` units.
+struct AudioVisualizer {
+    decoder: ffmpeg::codec::decoder::Audio,
+    /// To mono samples at the rate of the decoder.
+    resampler: ffmpeg::software::resampling::Context,
+    visualizer: Visualizer,
+    time_base: Rational,
+    /// Position of the sample after the last decoded one, for frames without a timestamp.
+    next_position: i64,
+}
+
+impl AudioVisualizer {
+    /// Get the next frame and its index, `None` until its samples are decoded.
+    fn receive_frame(&mut self, frame: &mut Video) -> Option<i64> {
+        let rate = Rational(1, self.decoder.rate() as i32);
+        let mut decoded = Audio::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let mut mono = Audio::empty();
+            self.resampler
+                .run(&decoded, &mut mono)
+                .expect("Input or output changed");
+            let position = match decoded.timestamp() {
+                Some(pts) => pts.rescale(self.time_base, rate),
+                None => self.next_position,
+            };
+            let samples: &[f32] = packed(&mono);
+            self.visualizer.push(position, samples);
+            self.next_position = position + samples.len() as i64;
+        }
+
+        let (index, pixels) = self.visualizer.next_frame()?;
+        *frame = Video::new(Pixel::RGB24, pixels.width as u32, pixels.height as u32);
+        let stride = frame.stride(0);
+        let data = frame.data_mut(0);
+        for (y, row) in pixels.rows().enumerate() {
+            data[y * stride..y * stride + row.len()].copy_from_slice(row);
+        }
+        frame.set_pts(Some(index));
+        Some(index)
+    }
+}
+
+/// Get the source of the frames of the video stream selected by the `input` options, with a
+/// scaler to `format` and `max_width`, the index of the stream, its time base and its frame rate.
+///
+/// Inputs without video (cover art doesn't count) are shown as the visualizer of the selected
+/// audio stream.
 fn get_video(
     ictx: &mut Input,
     scale_algorithm: ffmpeg_next::software::scaling::flag::Flags,
     format: Pixel,
     max_width: Option<f64>,
-) -> (ffmpeg::codec::decoder::Video, Context, usize) {
-    let video_input = ictx
-        .streams()
-        .best(MediaType::Video)
-        .expect("No video stream found");
+    input: &InputOptions,
+) -> (VideoSource, Context, usize, Rational, Rational) {
+    let Some(video_input) = streams::select_stream(ictx, MediaType::Video, &input.video) else {
+        let audio = streams::select_stream(ictx, MediaType::Audio, &input.audio)
+            .expect("There is a video or audio stream, see check_streams");
+        let decoder = get_audio_decoder(&audio);
+        let resampler = get_resampler(&decoder, ChannelLayout::MONO, decoder.rate());
+        let width = max_width.map_or(VISUALIZER_WIDTH, |max_width| {
+            (max_width as u32).clamp(2, VISUALIZER_WIDTH)
+        }) & !1;
+        let height = (width * 9 / 16).max(2) & !1;
+        let visualizer = Visualizer::new(
+            input.visualizer,
+            width as usize,
+            height as usize,
+            decoder.rate(),
+        );
+        let scaler = get_scaler(
+            Pixel::RGB24,
+            width,
+            height,
+            format,
+            scale_algorithm,
+            max_width,
+        );
+        let source = VideoSource::Audio(AudioVisualizer {
+            decoder,
+            resampler,
+            visualizer,
+            time_base: audio.time_base(),
+            next_position: 0,
+        });
+        let fps = visualize::VISUALIZER_FPS as i32;
+        return (
+            source,
+            scaler,
+            audio.index(),
+            Rational(1, fps),
+            Rational(fps, 1),
+        );
+    };
     let video_stream_index = video_input.index();
+    let (time_base, frame_rate) = (video_input.time_base(), stream_frame_rate(&video_input));
 
     let context_decoder =
         ffmpeg::codec::context::Context::from_parameters(video_input.parameters())
@@ -488,8 +1270,37 @@ fn get_video(
         .video()
         .expect("Couldn't find decoder");
 
+    // create scaler
+    let scaler = get_scaler(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        format,
+        scale_algorithm,
+        max_width,
+    );
+
+    (
+        VideoSource::Video(decoder),
+        scaler,
+        video_stream_index,
+        time_base,
+        frame_rate,
+    )
+}
+
+/// Get a scaler that converts frames of the given size to `format` and scales them down to
+/// `max_width` (keeping the aspect ratio).
+fn get_scaler(
+    src_format: Pixel,
+    src_width: u32,
+    src_height: u32,
+    format: Pixel,
+    scale_algorithm: ffmpeg_next::software::scaling::flag::Flags,
+    max_width: Option<f64>,
+) -> Context {
     let factor = if let Some(max_width) = max_width {
-        match decoder.width() as f64 {
+        match src_width as f64 {
             w if w <= max_width => 1.0,
             w => max_width / w,
         }
@@ -497,27 +1308,472 @@ fn get_video(
         1.0
     };
 
-    // create scaler
     let dst_width = if factor < 1.0 {
-        (decoder.width() as f64 * factor) as u32
+        (src_width as f64 * factor) as u32
     } else {
-        decoder.width()
+        src_width
     };
     let dst_height = if factor < 1.0 {
-        (decoder.height() as f64 * factor) as u32
+        (src_height as f64 * factor) as u32
     } else {
-        decoder.height()
+        src_height
     };
-    let scaler = Context::get(
-        decoder.format(),
-        decoder.width(),
-        decoder.height(),
+    Context::get(
+        src_format,
+        src_width,
+        src_height,
         format,
         dst_width,
         dst_height,
         scale_algorithm,
     )
-    .expect("Failed to get context");
+    .expect("Failed to get context")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::audio::SteppedSink;
+    use crate::testutil::{TempPath, av_input, tone_wav};
+
+    use ffmpeg::software::scaling::flag::Flags;
+
+    fn play_options(playback: &PlaybackOptions) -> PlayOptions<'_> {
+        PlayOptions {
+            scale_algorithm: Flags::POINT,
+            max_width: Some(32.0),
+            disable_audio: false,
+            fit_terminal: false,
+            playback: Some(playback),
+            format: Pixel::RGB24,
+            input: &playback.input,
+        }
+    }
+
+    #[test]
+    fn stepped_sink_paces_frames() {
+        let path = av_input(1);
+        let sink = SteppedSink::new();
+        let playback = PlaybackOptions {
+            // one frame
+            av_offset: 0.04,
+            controls: false,
+            audio: AudioBackend::Stepped(sink.clone()),
+            ..Default::default()
+        };
+        let mut presented = 0;
+        let stats = play(path.as_str(), &play_options(&playback), |_, time, _| {
+            let frame = presented;
+            assert_eq!((time.seconds() * 25.0).round() as u64, frame);
+            // frames are shown the offset before the audio reaches them, without waiting
+            let shown_at = frame.saturating_sub(1);
+            assert_eq!(sink.clock().frames(), shown_at * 1_920);
+            assert_eq!(sink.clock().now(), Duration::from_millis(shown_at * 40));
+            // the audio up to the frame is buffered by now, it's played to there
+            if frame > 0 {
+                assert_eq!(sink.advance(1_920).len(), 1_920 * 2);
+            }
+            presented += 1;
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(
+            stats,
+            FrameStats {
+                presented: 25,
+                dropped: 0
+            }
+        );
+    }
+
+    #[test]
+    fn wav_sink_records_the_range() {
+        let path = av_input(2);
+        let wav = TempPath::new("played.wav");
+        let playback = PlaybackOptions {
+            controls: false,
+            input: InputOptions {
+                range: TimeRange::with_duration(Duration::from_millis(500), Duration::from_secs(1)),
+                ..Default::default()
+            },
+            audio: AudioBackend::Wav(wav.path().to_owned()),
+            ..Default::default()
+        };
+        let mut timestamps = Vec::new();
+        let stats = play(path.as_str(), &play_options(&playback), |_, time, _| {
+            timestamps.push(time.seconds());
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+
+        assert_eq!(stats.presented + stats.dropped, 25);
+        assert_eq!(timestamps[0], 0.0);
+        // 16-bit stereo after the header
+        let frames = (std::fs::metadata(wav.path()).unwrap().len() - 44) / 4;
+        assert!(frames.abs_diff(48_000) <= 2_400, "{frames} frames");
+    }
+
+    #[test]
+    fn subtitles_are_timed_from_the_start_of_the_input() {
+        let time = FrameTime {
+            pts: 25,
+            time_base: Rational(1, 25),
+            frame_rate: Rational(25, 1),
+            duration_micros: 0,
+        };
+        let range = TimeRange::new(Duration::from_secs(10), None);
+        assert_eq!(subtitle_time(&time, &range), Duration::from_secs(11));
+    }
+
+    #[test]
+    fn subtitle_stream_is_shown() {
+        let path = crate::testutil::subtitled_video();
+        let input = InputOptions {
+            subtitles: SubtitleSource::Stream(StreamSelector::Language("eng".into())),
+            ..Default::default()
+        };
+        let options = PlayOptions {
+            scale_algorithm: Flags::POINT,
+            max_width: Some(32.0),
+            disable_audio: true,
+            fit_terminal: false,
+            playback: None,
+            format: Pixel::RGB24,
+            input: &input,
+        };
+        let mut shown = Vec::new();
+        play(path.as_str(), &options, |_, time, subtitles| {
+            let frame = (time.seconds() * 25.0).round() as u64;
+            shown.push((frame, subtitles.join("|")));
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        let expected: Vec<_> = (0..25)
+            .map(|frame| match frame * 40 {
+                100..500 => (frame, "Hello".to_string()),
+                610..900 => (frame, "Two|lines".to_string()),
+                _ => (frame, String::new()),
+            })
+            .collect();
+        assert_eq!(shown, expected);
+    }
+
+    #[test]
+    fn subtitles_are_printed_over_the_bottom_rows() {
+        let mut out = Vec::new();
+        print_subtitles(&mut out, &["Hi", "there", "cut off"], 12).unwrap();
+        let white_on_black = "\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m";
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "\x1b[2A\r\x1b[4C{white_on_black} Hi \x1b[0m\x1b[2B\r\
+                 \x1b[1A\r\x1b[2C{white_on_black} there \x1b[0m\x1b[1B\r"
+            )
+        );
+
+        let mut out = Vec::new();
+        print_subtitles(&mut out, &[], 12).unwrap();
+        assert!(out.is_empty());
+    }
+
+    /// Plays the second after 0.4 s of the 2 s tone at `path` to a stepped sink, returns the
+    /// stats and the number of frames (of samples) that were played.
+    fn play_tone_range(path: &str) -> (FrameStats, usize) {
+        let sink = SteppedSink::new();
+        let playback = PlaybackOptions {
+            controls: false,
+            input: InputOptions {
+                // in the middle of packets of the input, on frames of the visualization
+                range: TimeRange::with_duration(Duration::from_millis(400), Duration::from_secs(1)),
+                ..Default::default()
+            },
+            audio: AudioBackend::Stepped(sink.clone()),
+            ..Default::default()
+        };
+        let mut played = 0;
+        let stats = play(path, &play_options(&playback), |_, time, _| {
+            let frame = (time.seconds() * 25.0).round() as u64;
+            assert_eq!(sink.clock().frames(), frame * 1_920);
+            // the visualization of a frame is drawn once its audio is buffered
+            played += sink.advance(1_920).len() / 2;
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        played += sink.advance(48_000).len() / 2;
+        (stats, played)
+    }
+
+    #[test]
+    fn only_the_range_is_played() {
+        ffmpeg::init().unwrap();
+        let wav = TempPath::new("tone.wav");
+        tone_wav(wav.path(), 2);
+        let (stats, played) = play_tone_range(wav.as_str());
+        assert_eq!(stats.presented, 25);
+        assert_eq!(played, 48_000);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unseekable_input_is_decoded_up_to_the_start() {
+        use std::os::unix::io::AsRawFd;
+
+        ffmpeg::init().unwrap();
+        let wav = TempPath::new("tone.wav");
+        tone_wav(wav.path(), 2);
+        // a pipe can't seek
+        let file = std::fs::File::open(wav.path()).unwrap();
+        let (stats, played) = play_tone_range(&format!("pipe:{}", file.as_raw_fd()));
+        assert_eq!(stats.presented, 25);
+        assert_eq!(played, 48_000);
+    }
+
+    #[test]
+    fn selected_streams_are_played() {
+        let path = av_input(1);
+        let wav = TempPath::new("played.wav");
+        let mut playback = PlaybackOptions {
+            controls: false,
+            input: InputOptions {
+                video: StreamSelector::Index(0),
+                audio: StreamSelector::Index(1),
+                ..Default::default()
+            },
+            audio: AudioBackend::Wav(wav.path().to_owned()),
+            ..Default::default()
+        };
+        let stats = play(path.as_str(), &play_options(&playback), |_, _, _| {
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(stats.presented + stats.dropped, 25);
+        std::fs::remove_file(wav.path()).unwrap();
+
+        // the video stream isn't an audio stream, the input isn't played
+        playback.input.audio = StreamSelector::Index(0);
+        let result = play(path.as_str(), &play_options(&playback), |_, _, _| {
+            panic!("played without the selected audio stream")
+        });
+        let missing = StreamError::NotFound(MediaType::Audio, StreamSelector::Index(0));
+        assert_eq!(result, Err(missing));
+        assert!(!wav.path().exists());
+    }
+
+    #[test]
+    fn missing_streams_are_errors() {
+        let path = av_input(1);
+        let missing = |input: InputOptions| {
+            let playback = PlaybackOptions {
+                controls: false,
+                audio: AudioBackend::Null,
+                input,
+                ..Default::default()
+            };
+            play(path.as_str(), &play_options(&playback), |_, _, _| {
+                ControlFlow::Continue(())
+            })
+            .err()
+        };
+        let klingon = StreamSelector::Language("tlh".into());
+        assert_eq!(
+            missing(InputOptions {
+                video: klingon.clone(),
+                ..Default::default()
+            }),
+            Some(StreamError::NotFound(MediaType::Video, klingon))
+        );
+        assert_eq!(
+            missing(InputOptions {
+                subtitles: SubtitleSource::Stream(StreamSelector::Index(1)),
+                ..Default::default()
+            }),
+            Some(StreamError::NotFound(
+                MediaType::Subtitle,
+                StreamSelector::Index(1)
+            ))
+        );
+        assert_eq!(missing(InputOptions::default()), None);
+
+        let err = StreamError::NotFound(MediaType::Audio, StreamSelector::Index(0));
+        assert_eq!(err.to_string(), "there is no audio stream #0");
+    }
+
+    /// `RGB24` frame with a white vertical line at column `line` on a black background.
+    fn vertical_line(width: u32, height: u32, line: usize) -> Video {
+        let mut frame = Video::new(Pixel::RGB24, width, height);
+        let stride = frame.stride(0);
+        let data = frame.data_mut(0);
+        data.fill(0);
+        for y in 0..height as usize {
+            data[y * stride + line * 3..y * stride + line * 3 + 3].fill(255);
+        }
+        frame
+    }
 
-    (decoder, scaler, video_stream_index)
+    fn assert_rows_line_up(frame: &Frame, line: usize) {
+        for y in 0..frame.height {
+            let brightest = frame
+                .row_pixels(y)
+                .enumerate()
+                .max_by_key(|(_, (r, g, b))| *r as u16 + *g as u16 + *b as u16)
+                .unwrap()
+                .0;
+            assert_eq!(brightest, line, "row {y} of a {} wide frame", frame.width);
+        }
+    }
+
+    #[test]
+    fn as_frame_respects_stride() {
+        for width in [479, 481, 1001] {
+            let video = vertical_line(width, 9, width as usize / 2);
+            let frame = as_frame(&video);
+            assert_eq!(frame.width, width as usize);
+            assert_eq!(frame.height, 9);
+            assert_rows_line_up(&frame, width as usize / 2);
+        }
+    }
+
+    #[test]
+    fn audio_only_input_is_visualized() {
+        ffmpeg::init().unwrap();
+        let wav = TempPath::new("audio-only.wav");
+        tone_wav(wav.path(), 1);
+        let options = PlayOptions {
+            scale_algorithm: Flags::POINT,
+            max_width: Some(64.0),
+            disable_audio: true,
+            fit_terminal: false,
+            playback: None,
+            format: Pixel::RGB24,
+            input: &InputOptions::default(),
+        };
+        let mut timestamps = Vec::new();
+        play(wav.as_str(), &options, |frame, time, _| {
+            assert_eq!((frame.width(), frame.height()), (64, 36));
+            // the bars of the tone
+            assert!(as_frame(&frame).row(35).iter().any(|c| *c > 0));
+            timestamps.push(time.pts);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(timestamps, (0..25).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn audio_only_input_follows_the_audio_clock() {
+        ffmpeg::init().unwrap();
+        let wav = TempPath::new("audio-only.wav");
+        tone_wav(wav.path(), 1);
+        let sink = SteppedSink::new();
+        let playback = PlaybackOptions {
+            controls: false,
+            audio: AudioBackend::Stepped(sink.clone()),
+            ..Default::default()
+        };
+        let mut timestamps = Vec::new();
+        let stats = play(wav.as_str(), &play_options(&playback), |_, time, _| {
+            // each frame is shown when the audio reaches it
+            assert_eq!(sink.clock().frames(), time.pts as u64 * 1_920);
+            timestamps.push(time.pts);
+            assert_eq!(sink.advance(1_920).len(), 1_920 * 2);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(timestamps, (0..25).collect::<Vec<_>>());
+        assert_eq!(
+            stats,
+            FrameStats {
+                presented: 25,
+                dropped: 0
+            }
+        );
+    }
+
+    #[test]
+    fn audio_only_input_is_drawn_to_file() {
+        ffmpeg::init().unwrap();
+        let wav = TempPath::new("audio-only.wav");
+        tone_wav(wav.path(), 1);
+        let output = TempPath::new("drawn.mkv");
+        let font_data = crate::testutil::font_data();
+        let font = ab_glyph::FontRef::try_from_slice(&font_data).unwrap();
+        draw_to_file(
+            wav.as_str(),
+            output.as_str(),
+            &font,
+            Flags::POINT,
+            Some(16.0),
+            &EncoderOptions::default(),
+            &InputOptions::default(),
+        )
+        .unwrap();
+        let streams = streams::list_streams(output.as_str()).unwrap();
+        let media: Vec<_> = streams.iter().map(|stream| stream.medium).collect();
+        // the visualization and the audio it's drawn from
+        assert_eq!(media, [MediaType::Video, MediaType::Audio]);
+        assert_eq!(streams[1].sample_rate, Some(48_000));
+    }
+
+    #[test]
+    fn overlay_respects_stride() {
+        let mut video = vertical_line(481, 12, 100);
+        let loud: Vec<f32> = (0..visualize::FFT_LEN)
+            .map(|i| (i as f32 * 0.1).sin())
+            .collect();
+        draw_overlay(&mut video, Visualization::VuMeter, &loud);
+        let frame = as_frame(&video);
+        assert_rows_line_up(
+            &Frame::from_raw(481, 10, frame.stride, &frame.data).unwrap(),
+            100,
+        );
+        // the meter is green on the left
+        assert_eq!(frame.pixel(0, 11), (0, 255, 0));
+        assert_ne!(frame.pixel(400, 10), (0, 0, 0));
+    }
+
+    #[test]
+    fn scaled_frames_line_up() {
+        for width in [479, 481, 1001] {
+            let line = width as usize / 3;
+            let src = vertical_line(width, 20, line);
+            // not scaled
+            let mut scaler =
+                get_scaler(Pixel::RGB24, width, 20, Pixel::RGB24, Flags::POINT, None);
+            let mut dst = Video::empty();
+            scaler.run(&src, &mut dst).unwrap();
+            assert_rows_line_up(&as_frame(&dst), line);
+
+            // through yuv (like decoded video) and scaled down to an odd width
+            let mut to_yuv = get_scaler(
+                Pixel::RGB24,
+                width,
+                20,
+                Pixel::YUV420P,
+                Flags::POINT,
+                None,
+            );
+            let mut yuv = Video::empty();
+            to_yuv.run(&src, &mut yuv).unwrap();
+            let mut scaler = get_scaler(
+                Pixel::YUV420P,
+                width,
+                20,
+                Pixel::RGB24,
+                Flags::POINT,
+                Some(width as f64 / 2.0),
+            );
+            let mut dst = Video::empty();
+            scaler.run(&yuv, &mut dst).unwrap();
+            let frame = as_frame(&dst);
+            assert_eq!(frame.width, width as usize / 2);
+            let first = frame
+                .row_pixels(0)
+                .enumerate()
+                .max_by_key(|(_, (r, _, _))| *r)
+                .unwrap()
+                .0;
+            assert_rows_line_up(&frame, first);
+        }
+    }
 }
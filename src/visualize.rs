@@ -0,0 +1,516 @@
+use crate::Frame;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Samples of the audio the visualizations are computed from.
+pub const FFT_LEN: usize = 1024;
+
+/// Levels from this many decibels below full scale up to full scale are shown.
+const RANGE_DB: f32 = 60.0;
+
+/// Rows of the VU meter.
+const VU_ROWS: usize = 2;
+
+/// Frames per second of the visualizations of inputs without video.
+pub const VISUALIZER_FPS: u32 = 25;
+
+/// Overlay drawn along the bottom of the frames of a playback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visualization {
+    /// A bar as long as the loudness of the audio.
+    VuMeter,
+    /// Bars of the spectrum of the audio, low frequencies on the left.
+    Spectrum,
+}
+
+impl Visualization {
+    /// Get the visualization by its name, `vu` or `spectrum`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vu" => Some(Self::VuMeter),
+            "spectrum" => Some(Self::Spectrum),
+            _ => None,
+        }
+    }
+
+    /// Draws the visualization of the (mono) `samples` over the bottom rows of the `frame`.
+    ///
+    /// The bars are colored pixels, so they are mapped to symbols and colors like the rest of
+    /// the frame.
+    pub fn draw(self, frame: &mut Frame, samples: &[f32]) {
+        match self {
+            Self::VuMeter => draw_vu_meter(frame, vu_level(samples)),
+            Self::Spectrum => {
+                let rows = (frame.height / 4).clamp(1, 8);
+                draw_bars(frame, &spectrum(samples, frame.width), rows);
+            }
+        }
+    }
+}
+
+/// Visualization that's shown as the video of inputs without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioView {
+    /// The samples of every frame, like an oscilloscope.
+    Waveform,
+    /// Bars of the spectrum over the full height.
+    #[default]
+    Spectrum,
+    /// The spectrum of every frame as a row at the bottom, the older rows scroll up (a
+    /// waterfall).
+    Spectrogram,
+}
+
+impl AudioView {
+    /// Get the view by its name, `waveform`, `spectrum` or `spectrogram`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "waveform" => Some(Self::Waveform),
+            "spectrum" => Some(Self::Spectrum),
+            "spectrogram" => Some(Self::Spectrogram),
+            _ => None,
+        }
+    }
+}
+
+/// Generates the frames of an `AudioView` from the (mono) samples of an input, `VISUALIZER_FPS`
+/// per second.
+///
+/// Frame `n` is shown from `n / VISUALIZER_FPS` seconds on, it's drawn once the samples up to the
+/// next frame are pushed.
+#[derive(Debug, Clone)]
+pub struct Visualizer {
+    view: AudioView,
+    frame: Frame<'static>,
+    sample_rate: u32,
+    samples: VecDeque<f32>,
+    /// Position of the first sample in the input (in samples), `None` until samples are pushed.
+    offset: Option<i64>,
+    /// Index of the next frame.
+    next: i64,
+    /// Whether all samples are pushed.
+    ended: bool,
+}
+
+impl Visualizer {
+    /// Creates a visualizer of `width` x `height` frames for samples at `sample_rate`.
+    pub fn new(view: AudioView, width: usize, height: usize, sample_rate: u32) -> Self {
+        Self {
+            view,
+            frame: Frame::new(width, height),
+            sample_rate,
+            samples: VecDeque::new(),
+            offset: None,
+            next: 0,
+            ended: false,
+        }
+    }
+
+    /// Samples per frame.
+    fn period(&self) -> i64 {
+        (self.sample_rate / VISUALIZER_FPS).max(1) as i64
+    }
+
+    /// Appends the `samples`, the first one is at `position` (in samples) of the input.
+    ///
+    /// If they don't follow the pushed samples (give or take a frame), the visualization starts
+    /// over at `position`.
+    pub fn push(&mut self, position: i64, samples: &[f32]) {
+        let period = self.period();
+        let follows = self
+            .offset
+            .is_some_and(|offset| (offset + self.samples.len() as i64 - position).abs() <= period);
+        if !follows {
+            self.samples.clear();
+            self.offset = Some(position);
+            self.next = position.div_euclid(period);
+        }
+        self.samples.extend(samples);
+    }
+
+    /// Draws the remaining frames without waiting for more samples.
+    pub fn finish(&mut self) {
+        self.ended = true;
+    }
+
+    /// Drops the samples (after seeking), the next pushed samples start over.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.offset = None;
+        self.ended = false;
+    }
+
+    /// The next frame and its index, `None` until the samples of the frame are pushed.
+    pub fn next_frame(&mut self) -> Option<(i64, Frame<'static>)> {
+        let offset = self.offset?;
+        let period = self.period();
+        let available = offset + self.samples.len() as i64;
+        let end = (self.next + 1) * period;
+        if available < end && !(self.ended && available > self.next * period) {
+            return None;
+        }
+
+        // the spectrum needs more samples than a frame at low sample rates
+        let len = period.max(FFT_LEN as i64);
+        let window: Vec<f32> = (end - len..end)
+            .map(|i| match i - offset {
+                i if i >= 0 => self.samples.get(i as usize).copied().unwrap_or(0.0),
+                _ => 0.0,
+            })
+            .collect();
+        self.draw(&window, period as usize);
+
+        // the samples the next frame doesn't need
+        let unused = (end + period - len - offset).clamp(0, self.samples.len() as i64);
+        self.samples.drain(..unused as usize);
+        self.offset = Some(offset + unused);
+        self.next += 1;
+        Some((self.next - 1, self.frame.clone()))
+    }
+
+    /// Draws the `window` of samples, which ends with the `period` samples of the frame.
+    fn draw(&mut self, window: &[f32], period: usize) {
+        let (width, height) = (self.frame.width, self.frame.height);
+        match self.view {
+            AudioView::Waveform => {
+                self.frame.data.to_mut().fill(0);
+                draw_waveform(&mut self.frame, &window[window.len() - period..]);
+            }
+            AudioView::Spectrum => {
+                self.frame.data.to_mut().fill(0);
+                draw_bars(&mut self.frame, &spectrum(window, width), height);
+            }
+            AudioView::Spectrogram if height > 0 => {
+                let row = width * 3;
+                let data = self.frame.data.to_mut();
+                data.copy_within(row.., 0);
+                for (x, level) in spectrum(window, width).into_iter().enumerate() {
+                    set_bar_pixel(&mut self.frame, x, height - 1, level, level);
+                }
+            }
+            AudioView::Spectrogram => {}
+        }
+    }
+}
+
+/// The samples the audio output played last, mixed down to mono. Clones share the samples.
+#[derive(Debug, Clone, Default)]
+pub struct AudioMonitor {
+    samples: Arc<Mutex<VecDeque<f32>>>,
+}
+
+impl AudioMonitor {
+    /// Appends the interleaved `samples` of `channels` channels, only the last `FFT_LEN` are
+    /// kept.
+    ///
+    /// The samples are dropped while they are read, the audio callback can't wait.
+    pub fn push(&self, samples: impl IntoIterator<Item = f32>, channels: usize) {
+        let Ok(mut buffer) = self.samples.try_lock() else {
+            return;
+        };
+        let channels = channels.max(1);
+        let (mut sum, mut count) = (0.0, 0);
+        for sample in samples {
+            sum += sample;
+            count += 1;
+            if count == channels {
+                buffer.push_back(sum / channels as f32);
+                (sum, count) = (0.0, 0);
+            }
+        }
+        let excess = buffer.len().saturating_sub(FFT_LEN);
+        buffer.drain(..excess);
+    }
+
+    /// The last `len` samples, with silence before the first one.
+    pub fn latest(&self, len: usize) -> Vec<f32> {
+        let buffer = self.samples.lock().expect("monitor isn't poisoned");
+        let available = buffer.len().min(len);
+        let mut samples = vec![0.0; len - available];
+        samples.extend(buffer.range(buffer.len() - available..));
+        samples
+    }
+
+    /// Drops the samples (after seeking).
+    pub fn clear(&self) {
+        if let Ok(mut buffer) = self.samples.try_lock() {
+            buffer.clear();
+        }
+    }
+}
+
+/// In-place radix-2 FFT of the complex signal `re` + i * `im`, the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n);
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -std::f32::consts::TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let (re_b, im_b) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                re[b] = re[a] - re_b;
+                im[b] = im[a] - im_b;
+                re[a] += re_b;
+                im[a] += im_b;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Maps an amplitude (`1.0` is full scale) to `0.0..=1.0` on a decibel scale.
+fn level(amplitude: f32) -> f32 {
+    let db = 20.0 * amplitude.max(f32::MIN_POSITIVE).log10();
+    ((db + RANGE_DB) / RANGE_DB).clamp(0.0, 1.0)
+}
+
+/// Loudness (the RMS) of the `samples`, `0.0..=1.0`.
+pub fn vu_level(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let power = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    // the RMS of a full scale sine is 1 / sqrt(2)
+    level(power.sqrt() * std::f32::consts::SQRT_2)
+}
+
+/// Levels (`0.0..=1.0`) of `bands` logarithmically spaced frequency bands of the last `FFT_LEN`
+/// `samples`.
+pub fn spectrum(samples: &[f32], bands: usize) -> Vec<f32> {
+    let samples = &samples[samples.len().saturating_sub(FFT_LEN)..];
+    let mut re = vec![0.0; FFT_LEN];
+    let mut im = vec![0.0; FFT_LEN];
+    // Hann window
+    for (i, sample) in samples.iter().enumerate() {
+        let window = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FFT_LEN as f32).cos();
+        re[i] = sample * window;
+    }
+    fft(&mut re, &mut im);
+
+    let half = FFT_LEN / 2;
+    // a full scale sine has an amplitude of 1 (the window halves it)
+    let amplitudes: Vec<f32> = (0..half)
+        .map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt() * 4.0 / FFT_LEN as f32)
+        .collect();
+    let edge = |band: usize| (half as f32).powf(band as f32 / bands as f32) as usize;
+    (0..bands)
+        .map(|band| {
+            // without the DC bin, every band has at least one bin
+            let low = edge(band).clamp(1, half - 1);
+            let high = edge(band + 1).clamp(low + 1, half);
+            level(amplitudes[low..high].iter().copied().fold(0.0, f32::max))
+        })
+        .collect()
+}
+
+/// Color of a bar at `position` (`0.0..=1.0`) of its full length: green, yellow, then red.
+fn bar_color(position: f32) -> (f32, f32, f32) {
+    match position {
+        p if p < 0.5 => (p * 2.0, 1.0, 0.0),
+        p => (1.0, (2.0 - p * 2.0).max(0.0), 0.0),
+    }
+}
+
+/// Sets the pixel at `x`, `y` to the bar color at `position`, scaled by `fill`, the part of the
+/// pixel that's covered (dimmer pixels get lighter symbols).
+fn set_bar_pixel(frame: &mut Frame, x: usize, y: usize, position: f32, fill: f32) {
+    let (r, g, b) = bar_color(position);
+    let scale = |c: f32| (c * fill.clamp(0.0, 1.0) * 255.0).round() as u8;
+    frame.set_pixel(x, y, (scale(r), scale(g), scale(b)));
+}
+
+/// Draws a horizontal bar of `level` across the bottom rows of the `frame`.
+fn draw_vu_meter(frame: &mut Frame, level: f32) {
+    let length = level * frame.width as f32;
+    for y in frame.height.saturating_sub(VU_ROWS)..frame.height {
+        for x in 0..frame.width {
+            let fill = length - x as f32;
+            if fill > 0.0 {
+                set_bar_pixel(frame, x, y, x as f32 / frame.width as f32, fill);
+            }
+        }
+    }
+}
+
+/// Draws the `samples` from left to right, every column from the lowest to the highest of its
+/// samples (`1.0` at the top). Louder samples are red.
+fn draw_waveform(frame: &mut Frame, samples: &[f32]) {
+    if samples.is_empty() || frame.height == 0 {
+        return;
+    }
+    let middle = (frame.height - 1) as f32 / 2.0;
+    let y = |sample: f32| (middle - sample.clamp(-1.0, 1.0) * middle).round() as usize;
+    for x in 0..frame.width {
+        let start = x * samples.len() / frame.width;
+        let end = ((x + 1) * samples.len() / frame.width).max(start + 1);
+        let column = &samples[start..end.min(samples.len())];
+        let high = column.iter().copied().fold(f32::MIN, f32::max);
+        let low = column.iter().copied().fold(f32::MAX, f32::min);
+        for y in y(high)..=y(low) {
+            let position = (y as f32 - middle).abs() / middle.max(1.0);
+            set_bar_pixel(frame, x, y, position, 1.0);
+        }
+    }
+}
+
+/// Draws a vertical bar of every level (one per column) in the bottom `rows` rows of the `frame`.
+fn draw_bars(frame: &mut Frame, levels: &[f32], rows: usize) {
+    let rows = rows.min(frame.height);
+    for (x, level) in levels.iter().enumerate().take(frame.width) {
+        let length = level * rows as f32;
+        for row in 0..rows {
+            let fill = length - row as f32;
+            if fill > 0.0 {
+                let y = frame.height - 1 - row;
+                set_bar_pixel(frame, x, y, row as f32 / rows as f32, fill);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (std::f32::consts::TAU * frequency * i as f32).sin() * amplitude)
+            .collect()
+    }
+
+    #[test]
+    fn fft_finds_the_frequency() {
+        let mut re = sine(8.0 / 64.0, 1.0, 64);
+        let mut im = vec![0.0; 64];
+        fft(&mut re, &mut im);
+        let magnitudes: Vec<f32> = (0..32).map(|i| re[i].hypot(im[i])).collect();
+        let peak = (0..32)
+            .max_by(|a, b| magnitudes[*a].total_cmp(&magnitudes[*b]))
+            .unwrap();
+        assert_eq!(peak, 8);
+        assert!((magnitudes[8] - 32.0).abs() < 1e-3, "{}", magnitudes[8]);
+    }
+
+    #[test]
+    fn levels_of_sines() {
+        assert_eq!(vu_level(&[0.0; 256]), 0.0);
+        let full = vu_level(&sine(0.01, 1.0, 1000));
+        assert!(full > 0.98, "{full}");
+        // -20 dB
+        let quiet = vu_level(&sine(0.01, 0.1, 1000));
+        assert!((quiet - 40.0 / 60.0).abs() < 0.02, "{quiet}");
+
+        // a high sine is loudest in the last bands
+        let levels = spectrum(&sine(0.3, 1.0, FFT_LEN), 8);
+        let loudest = (0..8)
+            .max_by(|a, b| levels[*a].total_cmp(&levels[*b]))
+            .unwrap();
+        assert_eq!(loudest, 7);
+        assert!(levels[7] > 0.95, "{levels:?}");
+        assert!(levels[0] < 0.1, "{levels:?}");
+    }
+
+    #[test]
+    fn monitor_mixes_channels() {
+        let monitor = AudioMonitor::default();
+        monitor.push([1.0, 0.0, 0.5, 0.5, 0.25], 2);
+        // the half frame is dropped
+        assert_eq!(monitor.latest(3), [0.0, 0.5, 0.5]);
+        monitor.push(vec![0.1; FFT_LEN * 2], 1);
+        assert_eq!(monitor.latest(FFT_LEN * 2)[..FFT_LEN], [0.0; FFT_LEN]);
+        monitor.clear();
+        assert_eq!(monitor.latest(1), [0.0]);
+    }
+
+    #[test]
+    fn visualizer_waits_for_samples() {
+        // 40 samples per frame
+        let mut visualizer = Visualizer::new(AudioView::Spectrum, 8, 4, 1_000);
+        assert!(visualizer.next_frame().is_none());
+        visualizer.push(0, &[0.5; 100]);
+        assert_eq!(visualizer.next_frame().unwrap().0, 0);
+        assert_eq!(visualizer.next_frame().unwrap().0, 1);
+        assert!(visualizer.next_frame().is_none());
+        // the last frame is drawn without all of its samples
+        visualizer.finish();
+        assert_eq!(visualizer.next_frame().unwrap().0, 2);
+        assert!(visualizer.next_frame().is_none());
+
+        // a few samples off
+        visualizer.reset();
+        visualizer.push(0, &[0.0; 38]);
+        visualizer.push(41, &[0.0; 40]);
+        assert_eq!(visualizer.next_frame().unwrap().0, 0);
+        // after seeking
+        visualizer.push(4_020, &[0.0; 50]);
+        assert_eq!(visualizer.next_frame().unwrap().0, 100);
+        assert!(visualizer.next_frame().is_none());
+    }
+
+    #[test]
+    fn waveform_and_spectrogram() {
+        // 8 samples per frame, two per column
+        let mut visualizer = Visualizer::new(AudioView::Waveform, 4, 5, 200);
+        visualizer.push(0, &[1.0, -1.0, 0.0, 0.0, -1.0, -1.0, 0.5, 0.5]);
+        let (_, frame) = visualizer.next_frame().unwrap();
+        // from the top to the bottom
+        assert!((0..5).all(|y| frame.pixel(0, y) != (0, 0, 0)));
+        assert_eq!(frame.pixel(0, 0), (255, 0, 0));
+        assert_eq!(frame.pixel(1, 2), (0, 255, 0));
+        assert_eq!(frame.pixel(1, 1), (0, 0, 0));
+        assert_eq!(frame.pixel(2, 4), (255, 0, 0));
+        assert_eq!(frame.pixel(3, 1), (255, 255, 0));
+
+        let mut visualizer = Visualizer::new(AudioView::Spectrogram, 16, 3, 48_000);
+        visualizer.push(0, &sine(0.3, 1.0, 48_000 / 25 * 2));
+        let (_, first) = visualizer.next_frame().unwrap();
+        assert!(first.rows().take(2).all(|row| row.iter().all(|c| *c == 0)));
+        assert!(first.row(2).iter().any(|c| *c > 0));
+        let (_, second) = visualizer.next_frame().unwrap();
+        assert_eq!(second.row(1), first.row(2));
+    }
+
+    #[test]
+    fn bars_cover_the_bottom_rows() {
+        let mut frame = Frame::new(4, 6);
+        draw_bars(&mut frame, &[0.0, 0.5, 1.0, 0.25], 4);
+        // column 1 is two rows high, column 3 one
+        assert_eq!(frame.pixel(1, 5), (0, 255, 0));
+        assert_ne!(frame.pixel(1, 4), (0, 0, 0));
+        assert_eq!(frame.pixel(1, 3), (0, 0, 0));
+        assert_ne!(frame.pixel(3, 5), (0, 0, 0));
+        assert_eq!(frame.pixel(3, 4), (0, 0, 0));
+        // the top is red, nothing is drawn above the bars
+        assert_eq!(frame.pixel(2, 2).0, 255);
+        assert!(frame.rows().take(2).all(|row| row.iter().all(|c| *c == 0)));
+        assert!((0..6).all(|y| frame.pixel(0, y) == (0, 0, 0)));
+
+        let mut frame = Frame::new(10, 4);
+        draw_vu_meter(&mut frame, 0.35);
+        assert_ne!(frame.pixel(3, 3), (0, 0, 0));
+        // half covered
+        assert_eq!(frame.pixel(3, 2), frame.pixel(3, 3));
+        assert_eq!(frame.pixel(2, 3).1, 255);
+        assert_eq!(frame.pixel(3, 3).1, 128);
+        assert_eq!(frame.pixel(4, 3), (0, 0, 0));
+        assert_eq!(frame.pixel(0, 1), (0, 0, 0));
+    }
+}